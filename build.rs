@@ -0,0 +1,54 @@
+// rpg_bot - Telegram bot 🤖, help you to run and share Rust code in Telegram via Rust playground 🦀
+// Source code: <https://github.com/TheAwiteb/rpg_bot>
+//
+// Copyright (C) 2022 TheAwiteb <awiteb@hotmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Pass the versions of the migrations to the bot, the migrations are embedded in the binary (see `src/migrations.rs`)
+
+use std::fs;
+
+/// Returns the versions of the migrations in the directory, comma separated
+fn versions(directory: &str) -> String {
+    // rebuild when the migrations change, so the embedded migrations are up to date
+    println!("cargo:rerun-if-changed={}", directory);
+    let mut versions: Vec<String> = fs::read_dir(directory)
+        .unwrap_or_else(|err| panic!("Cannot read `{}` directory: {}", directory, err))
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| {
+            // the same as diesel, `2022-02-08-070912_users` version is `20220208070912`
+            entry
+                .file_name()
+                .to_str()?
+                .split('_')
+                .next()
+                .map(|version| version.replace('-', ""))
+        })
+        .collect();
+    versions.sort();
+    versions.join(",")
+}
+
+fn main() {
+    println!(
+        "cargo:rustc-env=SQLITE_MIGRATIONS={}",
+        versions("migrations")
+    );
+    println!(
+        "cargo:rustc-env=POSTGRES_MIGRATIONS={}",
+        versions("migrations_postgres")
+    );
+}
@@ -0,0 +1,26 @@
+CREATE TABLE snippets (
+    id INTEGER NOT NULL PRIMARY KEY,
+    user_id INTEGER NOT NULL references users(id),
+    source_code_id INTEGER NOT NULL references source_codes(id),
+    name VARCHAR NOT NULL,
+    tags VARCHAR NOT NULL DEFAULT "",
+    created_at TIMESTAMP NOT NULL,
+    UNIQUE (user_id, name)
+);
+
+-- Full-text search index of the snippets, the rowid is the snippet id
+CREATE VIRTUAL TABLE snippets_fts USING fts5(name, tags, source_code);
+
+CREATE TRIGGER snippets_fts_insert AFTER INSERT ON snippets BEGIN
+    INSERT INTO snippets_fts (rowid, name, tags, source_code)
+    VALUES (
+        new.id,
+        new.name,
+        new.tags,
+        (SELECT source_code FROM source_codes WHERE id = new.source_code_id)
+    );
+END;
+
+CREATE TRIGGER snippets_fts_delete AFTER DELETE ON snippets BEGIN
+    DELETE FROM snippets_fts WHERE rowid = old.id;
+END;
@@ -0,0 +1,4 @@
+ALTER TABLE source_codes ADD COLUMN hash VARCHAR;
+
+-- The same author can't have the same source code with the same options twice
+CREATE UNIQUE INDEX source_codes_user_id_hash ON source_codes (user_id, hash);
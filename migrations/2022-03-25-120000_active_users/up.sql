@@ -0,0 +1 @@
+ALTER TABLE users ADD COLUMN is_active BOOLEAN NOT NULL DEFAULT 1
@@ -0,0 +1,2 @@
+ALTER TABLE users DROP COLUMN button_delay;
+ALTER TABLE users DROP COLUMN command_delay;
@@ -0,0 +1,3 @@
+ALTER TABLE users ADD COLUMN attempts_window_start TIMESTAMP;
+-- start the attempts window of the users who have used attempts, so they are no longer locked out forever
+UPDATE users SET attempts_window_start = CURRENT_TIMESTAMP WHERE attempts > 0;
@@ -0,0 +1,11 @@
+-- The users table is created in the next migration, the foreign key of user_id is added there
+CREATE TABLE source_codes (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    code VARCHAR NOT NULL,
+    source_code TEXT NOT NULL,
+    version VARCHAR NOT NULL,
+    edition VARCHAR NOT NULL,
+    mode VARCHAR NOT NULL,
+    created_at TIMESTAMP NOT NULL
+)
@@ -0,0 +1,2 @@
+ALTER TABLE source_codes DROP CONSTRAINT source_codes_user_id_fkey;
+DROP TABLE users;
@@ -0,0 +1,5 @@
+CREATE TABLE config (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    value VARCHAR NOT NULL
+)
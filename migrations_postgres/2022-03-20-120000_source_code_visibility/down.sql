@@ -0,0 +1 @@
+ALTER TABLE source_codes DROP COLUMN is_public
@@ -0,0 +1 @@
+ALTER TABLE source_codes ADD COLUMN is_public BOOLEAN NOT NULL DEFAULT FALSE
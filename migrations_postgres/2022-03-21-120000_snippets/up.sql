@@ -0,0 +1,11 @@
+-- The full-text search is done with to_tsvector/to_tsquery on the snippets and their source codes,
+-- there is no FTS5 index table like SQLite
+CREATE TABLE snippets (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL references users(id),
+    source_code_id INTEGER NOT NULL references source_codes(id),
+    name VARCHAR NOT NULL,
+    tags VARCHAR NOT NULL DEFAULT '',
+    created_at TIMESTAMP NOT NULL,
+    UNIQUE (user_id, name)
+);
@@ -0,0 +1,2 @@
+DROP INDEX source_codes_user_id_hash;
+ALTER TABLE source_codes DROP COLUMN hash;
@@ -0,0 +1,8 @@
+CREATE TABLE cached_results (
+    id SERIAL PRIMARY KEY,
+    hash VARCHAR NOT NULL,
+    kind VARCHAR NOT NULL,
+    output TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    UNIQUE (hash, kind)
+)
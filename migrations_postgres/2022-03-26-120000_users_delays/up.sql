@@ -0,0 +1,2 @@
+ALTER TABLE users ADD COLUMN command_delay INTEGER;
+ALTER TABLE users ADD COLUMN button_delay INTEGER;
@@ -0,0 +1 @@
+ALTER TABLE users DROP COLUMN attempts_window_start
@@ -0,0 +1,4 @@
+DROP TABLE moderation_logs;
+ALTER TABLE users DROP COLUMN banned_until;
+ALTER TABLE users DROP COLUMN ban_reason;
+ALTER TABLE users DROP COLUMN is_shadow_banned;
@@ -0,0 +1,14 @@
+ALTER TABLE users ADD COLUMN is_shadow_banned BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE users ADD COLUMN ban_reason VARCHAR;
+ALTER TABLE users ADD COLUMN banned_until TIMESTAMP;
+
+-- Audit log of the moderation actions (ban, shadowban and unban)
+CREATE TABLE moderation_logs (
+    id SERIAL PRIMARY KEY,
+    admin_id INTEGER NOT NULL references users(id),
+    user_id INTEGER NOT NULL references users(id),
+    action VARCHAR NOT NULL,
+    reason VARCHAR,
+    until TIMESTAMP,
+    created_at TIMESTAMP NOT NULL
+);
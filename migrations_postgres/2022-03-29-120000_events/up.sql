@@ -0,0 +1,19 @@
+-- Usage events (run, share and callback), used by the statistics
+CREATE TABLE events (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL references users(id),
+    chat_id BIGINT NOT NULL,
+    kind VARCHAR NOT NULL,
+    command VARCHAR NOT NULL,
+    version VARCHAR,
+    mode VARCHAR,
+    edition VARCHAR,
+    -- playground request duration in milliseconds, null for cached results and callbacks
+    duration INTEGER,
+    success BOOLEAN NOT NULL,
+    output_size INTEGER NOT NULL,
+    is_cached BOOLEAN NOT NULL,
+    created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX events_created_at ON events (created_at);
@@ -0,0 +1,886 @@
+// rpg_bot - Telegram bot 🤖, help you to run and share Rust code in Telegram via Rust playground 🦀
+// Source code: <https://github.com/TheAwiteb/rpg_bot>
+//
+// Copyright (C) 2022 TheAwiteb <awiteb@hotmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{
+    errors::RpgError,
+    keyboards,
+    metrics::LogDbError,
+    models::{
+        Config, DieselError, DieselResult, KnownConfig, ModerationLog, NewModerationLog, Users,
+    },
+    rpg_db::{self, languages_ctx, DbConnection, DbPool},
+    scheduler,
+    settings::SETTINGS,
+    stats,
+};
+use chrono::{offset, Duration as ChronoDuration, NaiveDateTime};
+use json_gettext::get_text;
+use std::{collections::HashMap, time::Duration};
+use strfmt::strfmt;
+use teloxide::{
+    prelude2::*,
+    types::{InlineKeyboardMarkup, MessageId, ReplyMarkup},
+    ApiError, RequestError,
+};
+
+/// Commands that only admins can use
+const ADMIN_COMMANDS: [&str; 11] = [
+    "admin",
+    "ban",
+    "shadowban",
+    "unban",
+    "modlog",
+    "setlimit",
+    "user",
+    "config",
+    "stats",
+    "jobs",
+    "broadcast",
+];
+/// Delay between every broadcast message, to respect telegram rate limits (30 messages per second)
+const BROADCAST_DELAY: Duration = Duration::from_millis(50);
+/// Number of sent messages between every progress message update
+const BROADCAST_PROGRESS_STEP: usize = 25;
+/// Maximum number of moderation actions in `/modlog`
+const MODLOG_MAXIMUM: i64 = 10;
+
+/// Returns `true` if the command is admin command
+pub fn is_admin_command(command: &str) -> bool {
+    ADMIN_COMMANDS.contains(&command)
+}
+
+/// Returns the text of `key` formatted with `vars`
+fn format_text(key: &str, vars: &HashMap<String, String>, language: &str) -> String {
+    let ctx = languages_ctx();
+    strfmt(&get_text!(ctx, language, key).unwrap().to_string(), vars).unwrap()
+}
+
+/// Returns the text of `key` formatted with the `user` mention
+fn user_text(key: &str, user: &Users, language: &str) -> String {
+    let mut vars: HashMap<String, String> = HashMap::new();
+    vars.insert("user".into(), user.mention());
+    format_text(key, &vars, language)
+}
+
+/// Returns admin help message, with the admins list
+fn admin_help(language: &str, conn: &mut DbConnection) -> String {
+    let mut vars: HashMap<String, String> = HashMap::new();
+    vars.insert(
+        "admins".into(),
+        Users::admins(conn)
+            .unwrap_or_default()
+            .iter()
+            .map(|admin| format!("- {} ({})", admin.mention(), admin.telegram_id))
+            .collect::<Vec<String>>()
+            .join("\n"),
+    );
+    format_text("ADMIN_HELP", &vars, language)
+}
+
+/// Returns the answer of `/admin add <user>` and `/admin remove <user>`
+fn admin_answer(args: &[&str], author: &Users, conn: &mut DbConnection) -> String {
+    match (args.first().copied(), args.get(1)) {
+        (Some(action @ ("add" | "remove")), Some(query)) => match Users::find(query, conn) {
+            // the admins of the settings are promoted again on their next update
+            Ok(user)
+                if action == "remove" && SETTINGS.telegram.admins.contains(&user.telegram_id) =>
+            {
+                user_text("ADMIN_IN_SETTINGS", &user, &author.language)
+            }
+            Ok(mut user) => match user.set_admin(action == "add", conn) {
+                Ok(()) => user_text(
+                    if user.is_admin {
+                        "ADMIN_ADDED"
+                    } else {
+                        "ADMIN_REMOVED"
+                    },
+                    &user,
+                    &author.language,
+                ),
+                Err(err) => user_error_text(query, err, &author.language),
+            },
+            Err(err) => user_error_text(query, err, &author.language),
+        },
+        _ => admin_help(&author.language, conn),
+    }
+}
+
+/// Returns user not found message
+fn not_found_text(query: &str, language: &str) -> String {
+    let mut vars: HashMap<String, String> = HashMap::new();
+    vars.insert("user".into(), query.to_string());
+    format_text("USER_NOT_FOUND", &vars, language)
+}
+
+/// Returns user not found message if the user is not found, the database error message otherwise
+fn user_error_text(query: &str, err: DieselError, language: &str) -> String {
+    match err {
+        DieselError::NotFound => not_found_text(query, language),
+        err => {
+            let err: RpgError = err.into();
+            err.log();
+            err.user_message(language)
+        }
+    }
+}
+
+/// Parse ban duration like `30m`, `12h`, `7d` and `2w`
+fn parse_duration(duration: &str) -> Option<ChronoDuration> {
+    // the unit is the last character, the duration may be a reason in any language
+    let (unit_index, _) = duration.char_indices().last()?;
+    let (number, unit) = duration.split_at(unit_index);
+    let number: i64 = number.parse().ok().filter(|number| *number > 0)?;
+    match unit {
+        "m" => ChronoDuration::try_minutes(number),
+        "h" => ChronoDuration::try_hours(number),
+        "d" => ChronoDuration::try_days(number),
+        "w" => ChronoDuration::try_weeks(number),
+        _ => None,
+    }
+}
+
+/// Returns the ban reason and expiry lines of the user, empty if there is no reason and expiry
+pub fn ban_details(user: &Users, language: &str) -> String {
+    let mut vars: HashMap<String, String> = HashMap::new();
+    let mut details: String = String::new();
+    if let Some(reason) = &user.ban_reason {
+        vars.insert("reason".into(), reason.clone());
+        details += &("\n".to_string() + &format_text("BAN_REASON", &vars, language));
+    }
+    if let Some(until) = user.banned_until {
+        vars.insert(
+            "until".into(),
+            until.format("%Y-%m-%d %H:%M UTC").to_string(),
+        );
+        details += &("\n".to_string() + &format_text("BAN_UNTIL", &vars, language));
+    }
+    details
+}
+
+/// Returns the answer of `/ban`, `/shadowban` and `/unban`, the ban and shadowban arguments are
+/// `<user> <duration (optional)> <reason (optional)>`, `reason_args` are the original (not
+/// lowercased) arguments to take the reason from
+fn ban_answer(
+    command: &str,
+    args: &[&str],
+    reason_args: &[&str],
+    author: &Users,
+    conn: &mut DbConnection,
+) -> String {
+    let query: &str = match args.first() {
+        Some(query) => query,
+        None => return admin_help(&author.language, conn),
+    };
+    let mut user: Users = match Users::find(query, conn) {
+        Ok(user) => user,
+        Err(err) => return user_error_text(query, err, &author.language),
+    };
+
+    let (until, reason): (Option<NaiveDateTime>, Option<String>) = if command == "unban" {
+        (None, None)
+    } else {
+        // a duration that is too long is not a duration, it's a part of the reason
+        let until: Option<NaiveDateTime> = args
+            .get(1)
+            .and_then(|arg| parse_duration(arg))
+            .and_then(|duration| offset::Utc::now().naive_utc().checked_add_signed(duration));
+        let reason: String = reason_args
+            .iter()
+            .skip(if until.is_some() { 2 } else { 1 })
+            .copied()
+            .collect::<Vec<&str>>()
+            .join(" ");
+        (until, Some(reason).filter(|reason| !reason.is_empty()))
+    };
+
+    let result: DieselResult<()> = if command == "unban" {
+        user.unban(conn)
+    } else {
+        user.ban(command == "shadowban", reason.as_deref(), until, conn)
+    }
+    .and_then(|_| {
+        NewModerationLog::new(author, &user, command, reason.as_deref(), until).save(conn)
+    });
+
+    match result {
+        Ok(()) => {
+            user_text(
+                match command {
+                    "ban" => "USER_BANNED",
+                    "shadowban" => "USER_SHADOW_BANNED",
+                    _ => "USER_UNBANNED",
+                },
+                &user,
+                &author.language,
+            ) + &ban_details(&user, &author.language)
+        }
+        Err(err) => {
+            log::error!("Cannot {} {}", command, user.telegram_id);
+            user_error_text(query, err, &author.language)
+        }
+    }
+}
+
+/// Returns the answer of `/modlog <user (optional)>`, the latest moderation actions
+fn modlog_answer(query: Option<&str>, author: &Users, conn: &mut DbConnection) -> String {
+    let user: Option<Users> = match query.map(|query| (query, Users::find(query, conn))) {
+        Some((_, Ok(user))) => Some(user),
+        Some((query, Err(err))) => return user_error_text(query, err, &author.language),
+        None => None,
+    };
+    let logs: Vec<ModerationLog> =
+        ModerationLog::latest(user.as_ref(), MODLOG_MAXIMUM, conn).unwrap_or_default();
+    if logs.is_empty() {
+        return format_text("MODLOG_EMPTY", &HashMap::new(), &author.language);
+    }
+
+    let mut vars: HashMap<String, String> = HashMap::new();
+    vars.insert(
+        "logs".into(),
+        logs.iter()
+            .map(|log| {
+                let mention = |user: DieselResult<Users>| {
+                    user.map(|user| user.mention())
+                        .unwrap_or_else(|_| "-".to_string())
+                };
+                format!(
+                    "- {} {} {} {}{}{}",
+                    log.created_at.format("%Y-%m-%d %H:%M"),
+                    mention(log.admin(conn)),
+                    log.action,
+                    mention(log.user(conn)),
+                    log.until
+                        .map(|until| format!(" ({})", until.format("%Y-%m-%d %H:%M")))
+                        .unwrap_or_default(),
+                    log.reason
+                        .as_ref()
+                        .map(|reason| format!(": {}", reason))
+                        .unwrap_or_default(),
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n"),
+    );
+    format_text("MODLOG_MESSAGE", &vars, &author.language)
+}
+
+/// Returns the answer of `/setlimit <user> <attempts_maximum>`
+fn setlimit_answer(args: &[&str], author: &Users, conn: &mut DbConnection) -> String {
+    match (args.first(), args.get(1).map(|value| value.parse::<i32>())) {
+        (Some(query), Some(Ok(attempts_maximum))) if attempts_maximum >= 0 => {
+            match Users::find(query, conn).and_then(|mut user| {
+                user.set_attempts_maximum(attempts_maximum, conn)
+                    .map(|_| user)
+            }) {
+                Ok(user) => {
+                    let mut vars: HashMap<String, String> = HashMap::new();
+                    vars.insert("user".into(), user.mention());
+                    vars.insert("value".into(), attempts_maximum.to_string());
+                    format_text("LIMIT_SET", &vars, &author.language)
+                }
+                Err(err) => user_error_text(query, err, &author.language),
+            }
+        }
+        (Some(_), Some(_)) => invalid_value_text(args[1], &author.language),
+        _ => admin_help(&author.language, conn),
+    }
+}
+
+/// Returns the user limits text, the delays that come from the config will be marked as default
+fn user_info_text(user: &Users, language: &str, conn: &mut DbConnection) -> String {
+    let ctx = languages_ctx();
+    let default: String = get_text!(ctx, language, "DEFAULT").unwrap().to_string();
+    let delay_text = |delay: Option<i32>, current_delay: i64| match delay {
+        Some(_) => current_delay.to_string(),
+        None => format!("{} ({})", current_delay, default),
+    };
+
+    let mut vars: HashMap<String, String> = HashMap::new();
+    vars.insert("user".into(), user.mention());
+    vars.insert("telegram_id".into(), user.telegram_id.clone());
+    vars.insert("attempts".into(), user.attempts.to_string());
+    vars.insert("attempts_maximum".into(), user.attempts_maximum.to_string());
+    vars.insert(
+        "command_delay".into(),
+        delay_text(user.command_delay, user.current_command_delay(conn)),
+    );
+    vars.insert(
+        "button_delay".into(),
+        delay_text(user.button_delay, user.current_button_delay(conn)),
+    );
+    format_text("USER_INFO", &vars, language)
+}
+
+/// Returns the answer of `/user <user> <action (optional)> <value (optional)>`
+fn user_answer(args: &[&str], author: &Users, conn: &mut DbConnection) -> String {
+    let query: &str = match args.first() {
+        Some(query) => query,
+        None => return admin_help(&author.language, conn),
+    };
+    let mut user: Users = match Users::find(query, conn) {
+        Ok(user) => user,
+        Err(err) => return user_error_text(query, err, &author.language),
+    };
+    let action: Option<&str> = args.get(1).copied();
+    let value: Option<&str> = args.get(2).copied();
+
+    let result: Option<DieselResult<()>> = match (action, value) {
+        (None, _) => None,
+        (Some("reset"), _) => Some(user.reset_counters(conn)),
+        (Some("attempts"), Some(value)) => match value.parse::<i32>() {
+            Ok(attempts_maximum) if attempts_maximum >= 0 => {
+                Some(user.set_attempts_maximum(attempts_maximum, conn))
+            }
+            _ => return invalid_value_text(value, &author.language),
+        },
+        (Some(delay_name @ ("command_delay" | "button_delay")), Some(value)) => {
+            let new_delay: Option<i32> = if value == "default" {
+                None
+            } else {
+                // unwrap because the delays are known configs
+                match KnownConfig::get(delay_name).unwrap().validate(value) {
+                    Ok(delay) => Some(delay as i32),
+                    Err(_) => return invalid_value_text(value, &author.language),
+                }
+            };
+            Some(if delay_name == "command_delay" {
+                user.set_command_delay(new_delay, conn)
+            } else {
+                user.set_button_delay(new_delay, conn)
+            })
+        }
+        _ => return admin_help(&author.language, conn),
+    };
+
+    match result {
+        Some(Err(err)) => {
+            log::error!("Cannot update {}", user.telegram_id);
+            user_error_text(query, err, &author.language)
+        }
+        Some(Ok(())) => {
+            user_text("USER_UPDATED", &user, &author.language)
+                + "\n\n"
+                + &user_info_text(&user, &author.language, conn)
+        }
+        None => user_info_text(&user, &author.language, conn),
+    }
+}
+
+/// Returns invalid value message
+fn invalid_value_text(value: &str, language: &str) -> String {
+    let mut vars: HashMap<String, String> = HashMap::new();
+    vars.insert("value".into(), value.to_string());
+    format_text("INVALID_VALUE", &vars, language)
+}
+
+/// Returns the text of the known config with its value, default value and allowed range
+fn config_text(known: &KnownConfig, value: i64, language: &str) -> String {
+    let mut vars: HashMap<String, String> = HashMap::new();
+    vars.insert("name".into(), known.name.to_string());
+    vars.insert("value".into(), value.to_string());
+    vars.insert("default".into(), known.default_value().to_string());
+    vars.insert("minimum".into(), known.minimum.to_string());
+    vars.insert("maximum".into(), known.maximum.to_string());
+    format_text("CONFIG_VALUE", &vars, language)
+}
+
+/// Returns the answer of `/config`, `/config <name>` and `/config <name> <value>` with its keyboard
+/// (also used by the config keyboard callbacks)
+pub fn config_answer(
+    args: &[&str],
+    author: &Users,
+    conn: &mut DbConnection,
+) -> (String, InlineKeyboardMarkup) {
+    let mut vars: HashMap<String, String> = HashMap::new();
+    match (
+        args.first().map(|name| (name, KnownConfig::get(name))),
+        args.get(1),
+    ) {
+        (Some((_, Some(known))), value) => {
+            let result = match value {
+                Some(value) => Config::set(known.name, value, conn).map(|_| ()),
+                None => Ok(()),
+            };
+            let current_value: i64 = Config::integer(known.name, conn);
+            let text: String = config_text(known, current_value, &author.language);
+            let keyboard = keyboards::config_keyboard(known, current_value, &author.language);
+
+            match (result, value) {
+                (Ok(()), Some(_)) => {
+                    vars.insert("name".into(), known.name.to_string());
+                    vars.insert("value".into(), current_value.to_string());
+                    (
+                        format_text("CONFIG_SET", &vars, &author.language) + "\n\n" + &text,
+                        keyboard,
+                    )
+                }
+                (Err(RpgError::Validation(_)), Some(value)) => {
+                    vars.insert("value".into(), value.to_string());
+                    (
+                        format_text("INVALID_VALUE", &vars, &author.language) + "\n\n" + &text,
+                        keyboard,
+                    )
+                }
+                (Err(err), _) => {
+                    err.log();
+                    (
+                        err.user_message(&author.language) + "\n\n" + &text,
+                        keyboard,
+                    )
+                }
+                _ => (text, keyboard),
+            }
+        }
+        (Some((name, None)), _) => {
+            vars.insert("name".into(), name.to_string());
+            (
+                format_text("CONFIG_NOT_FOUND", &vars, &author.language),
+                InlineKeyboardMarkup::default(),
+            )
+        }
+        (None, _) => {
+            let configs: Vec<(&KnownConfig, i64)> = Config::all(conn);
+            vars.insert(
+                "configs".into(),
+                configs
+                    .iter()
+                    .map(|(known, value)| format!("{}: {}", known.name, value))
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+            );
+            (
+                format_text("CONFIG_LIST", &vars, &author.language),
+                keyboards::configs_keyboard(&configs),
+            )
+        }
+    }
+}
+
+/// Returns the answer of `/stats <period (default: week)>` with the periods keyboard
+fn stats_answer(
+    args: &[&str],
+    author: &Users,
+    conn: &mut DbConnection,
+) -> (String, InlineKeyboardMarkup) {
+    let period: &str = args.first().copied().unwrap_or(stats::DEFAULT_PERIOD);
+    match stats::stats_text(period, &author.language, conn) {
+        Ok(Some(text)) => (text, keyboards::stats_keyboard(period)),
+        Err(err) => {
+            let err: RpgError = err.into();
+            err.log();
+            (
+                err.user_message(&author.language),
+                keyboards::stats_keyboard(period),
+            )
+        }
+        Ok(None) => (
+            invalid_value_text(period, &author.language),
+            keyboards::stats_keyboard(stats::DEFAULT_PERIOD),
+        ),
+    }
+}
+
+/// Returns the answer of the admin commands that have keyboard (`config` and `stats`)
+fn keyboard_answer(
+    command: &str,
+    args: &[&str],
+    author: &Users,
+    conn: &mut DbConnection,
+) -> (String, InlineKeyboardMarkup) {
+    if command == "stats" {
+        stats_answer(args, author, conn)
+    } else {
+        config_answer(args, author, conn)
+    }
+}
+
+/// Admin keyboards callback (`config` and `stats`), edit the message with the command answer
+pub async fn callback_handler(
+    bot: &AutoSend<Bot>,
+    callback_query: &CallbackQuery,
+    command: &str,
+    args: &[&str],
+    author: &Users,
+    conn: &mut DbConnection,
+) {
+    if !author.is_admin {
+        let ctx = languages_ctx();
+        return bot
+            .answer_callback_query(&callback_query.id)
+            .text(
+                get_text!(ctx, &author.language, "NOT_ADMIN")
+                    .unwrap()
+                    .to_string()
+                    + " 🤨",
+            )
+            .send()
+            .await
+            .log_on_error()
+            .await;
+    }
+
+    let (text, keyboard) = keyboard_answer(command, args, author, conn);
+    // unwrap here because every callback query have message 🙂
+    let message: &Message = callback_query.message.as_ref().unwrap();
+    bot.answer_callback_query(&callback_query.id)
+        .send()
+        .await
+        .log_on_error()
+        .await;
+    bot.edit_message_text(message.chat.id, message.id, text)
+        .reply_markup(keyboard)
+        .send()
+        .await
+        .log_on_error()
+        .await;
+}
+
+/// Copy the message to the chat, wait and try again if exceeding flood control
+async fn copy_message(
+    bot: &AutoSend<Bot>,
+    message: &Message,
+    chat_id: i64,
+) -> Result<MessageId, RequestError> {
+    loop {
+        let request = bot.copy_message(chat_id, message.chat.id, message.id);
+        let result = if let Some(keyboard) = message.reply_markup() {
+            request
+                .reply_markup(ReplyMarkup::InlineKeyboard(keyboard.clone()))
+                .send()
+                .await
+        } else {
+            request.send().await
+        };
+        match result {
+            Err(RequestError::RetryAfter(seconds)) => {
+                tokio::time::sleep(Duration::from_secs(seconds as u64)).await
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Returns broadcast progress message
+fn broadcast_text(
+    key: &str,
+    (sent, failed, blocked, total): (usize, usize, usize, usize),
+    language: &str,
+) -> String {
+    let mut vars: HashMap<String, String> = HashMap::new();
+    vars.insert("sent".into(), sent.to_string());
+    vars.insert("failed".into(), failed.to_string());
+    vars.insert("blocked".into(), blocked.to_string());
+    vars.insert("total".into(), total.to_string());
+    format_text(key, &vars, language)
+}
+
+/// Broadcast the replied message to all active users, `/broadcast dry` only counts the recipients.
+/// The broadcast runs in its own task, see [`broadcast`]
+async fn broadcast_handler(
+    bot: &AutoSend<Bot>,
+    message: &Message,
+    args: &[&str],
+    author: &Users,
+    pool: &DbPool,
+    conn: &mut DbConnection,
+) {
+    let ctx = languages_ctx();
+    let users: Vec<Users> = match Users::active(conn) {
+        Ok(users) => users,
+        Err(err) => {
+            return RpgError::from(err)
+                .report_message(bot, message, &author.language)
+                .await
+        }
+    };
+    let total: usize = users.len();
+
+    let broadcast_message: &Message = match (args.first(), message.reply_to_message()) {
+        (Some(&"dry"), _) => {
+            let mut vars: HashMap<String, String> = HashMap::new();
+            vars.insert("total".into(), total.to_string());
+            return reply(
+                bot,
+                message,
+                format_text("BROADCAST_DRY_RUN", &vars, &author.language),
+            )
+            .await;
+        }
+        (_, Some(broadcast_message)) => broadcast_message,
+        (_, None) => {
+            return reply(
+                bot,
+                message,
+                get_text!(ctx, &author.language, "REPLY_MESSAGE")
+                    .unwrap()
+                    .to_string(),
+            )
+            .await
+        }
+    };
+
+    let progress_message: Message = match bot
+        .send_message(
+            message.chat.id,
+            broadcast_text("BROADCAST_PROGRESS", (0, 0, 0, total), &author.language),
+        )
+        .reply_to_message_id(message.id)
+        .send()
+        .await
+    {
+        Ok(progress_message) => progress_message,
+        Err(err) => {
+            log::error!("Cannot send broadcast progress message: {}", err);
+            return;
+        }
+    };
+
+    tokio::spawn(broadcast(
+        bot.clone(),
+        broadcast_message.clone(),
+        progress_message,
+        users,
+        author.language.clone(),
+        pool.clone(),
+    ));
+}
+
+/// Send the broadcast message to the users and update the progress message, the database
+/// connection is taken from the pool only to mark the users who blocked the bot as inactive
+async fn broadcast(
+    bot: AutoSend<Bot>,
+    broadcast_message: Message,
+    progress_message: Message,
+    users: Vec<Users>,
+    language: String,
+    pool: DbPool,
+) {
+    let total: usize = users.len();
+    let (mut sent, mut failed, mut blocked): (usize, usize, usize) = (0, 0, 0);
+    for (idx, mut user) in users.into_iter().enumerate() {
+        match copy_message(
+            &bot,
+            &broadcast_message,
+            user.telegram_id.parse().unwrap_or_default(),
+        )
+        .await
+        {
+            Ok(_) => sent += 1,
+            Err(RequestError::Api(
+                ApiError::BotBlocked
+                | ApiError::UserDeactivated
+                | ApiError::ChatNotFound
+                | ApiError::CantInitiateConversation,
+            )) => {
+                // the user blocked the bot, skip them until they come back
+                failed += 1;
+                blocked += 1;
+                match rpg_db::connection(&pool).await {
+                    Ok(mut conn) => user.set_active(false, &mut conn).log_db_error(),
+                    Err(err) => err.log(),
+                }
+            }
+            Err(err) => {
+                failed += 1;
+                log::warn!("Cannot broadcast to {}: {}", user.telegram_id, err);
+            }
+        };
+
+        if (idx + 1) % BROADCAST_PROGRESS_STEP == 0 {
+            bot.edit_message_text(
+                progress_message.chat.id,
+                progress_message.id,
+                broadcast_text(
+                    "BROADCAST_PROGRESS",
+                    (sent, failed, blocked, total),
+                    &language,
+                ),
+            )
+            .send()
+            .await
+            .log_on_error()
+            .await;
+        }
+        tokio::time::sleep(BROADCAST_DELAY).await;
+    }
+
+    bot.edit_message_text(
+        progress_message.chat.id,
+        progress_message.id,
+        broadcast_text("BROADCAST_DONE", (sent, failed, blocked, total), &language),
+    )
+    .send()
+    .await
+    .log_on_error()
+    .await;
+}
+
+/// Reply to the message with the text
+async fn reply(bot: &AutoSend<Bot>, message: &Message, text: String) {
+    bot.send_message(message.chat.id, text)
+        .reply_to_message_id(message.id)
+        .send()
+        .await
+        .log_on_error()
+        .await;
+}
+
+/// Returns the answer of `/jobs`, the background jobs with their intervals and last runs
+fn jobs_answer(language: &str, conn: &mut DbConnection) -> String {
+    let ctx = languages_ctx();
+    let jobs: Vec<String> = scheduler::JOBS
+        .iter()
+        .map(|job| {
+            let mut vars: HashMap<String, String> = HashMap::new();
+            vars.insert("name".into(), job.name.into());
+            let interval: i64 = Config::integer(job.interval_config, conn);
+            vars.insert("seconds".into(), interval.to_string());
+            let interval_text: String = if interval > 0 {
+                format_text("JOB_INTERVAL", &vars, language)
+            } else {
+                get_text!(ctx, language, "JOB_DISABLED")
+                    .unwrap()
+                    .to_string()
+            };
+            vars.insert("interval".into(), interval_text);
+
+            let last_run: String = match job.last_run() {
+                Some(run) => {
+                    vars.insert(
+                        "time".into(),
+                        run.time.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+                    );
+                    vars.insert("duration".into(), run.duration.as_millis().to_string());
+                    match run.result {
+                        Ok(affected) => {
+                            vars.insert("affected".into(), affected.to_string());
+                            format_text("JOB_SUCCESS", &vars, language)
+                        }
+                        Err(err) => {
+                            vars.insert("error".into(), err);
+                            format_text("JOB_FAILED", &vars, language)
+                        }
+                    }
+                }
+                None => get_text!(ctx, language, "JOB_NEVER_RUN")
+                    .unwrap()
+                    .to_string(),
+            };
+            vars.insert("last_run".into(), last_run);
+            format_text("JOB_STATUS", &vars, language)
+        })
+        .collect();
+
+    let mut vars: HashMap<String, String> = HashMap::new();
+    vars.insert("jobs".into(), jobs.join("\n\n"));
+    format_text("JOBS_LIST", &vars, language)
+}
+
+/// Admin commands handler, users who are not admins will get a permission error
+pub async fn command_handler(
+    bot: &AutoSend<Bot>,
+    message: &Message,
+    command: &str,
+    args: &[&str],
+    author: &Users,
+    pool: &DbPool,
+    conn: &mut DbConnection,
+) {
+    if author.is_admin && command == "broadcast" {
+        return broadcast_handler(bot, message, args, author, pool, conn).await;
+    } else if author.is_admin && ["config", "stats"].contains(&command) {
+        let (text, keyboard) = keyboard_answer(command, args, author, conn);
+        return bot
+            .send_message(message.chat.id, text)
+            .reply_to_message_id(message.id)
+            .reply_markup(keyboard)
+            .send()
+            .await
+            .log_on_error()
+            .await;
+    }
+
+    let text: String = if !author.is_admin {
+        let ctx = languages_ctx();
+        get_text!(ctx, &author.language, "NOT_ADMIN")
+            .unwrap()
+            .to_string()
+            + " 🤨"
+    } else {
+        match command {
+            "admin" => admin_answer(args, author, conn),
+            "ban" | "shadowban" | "unban" => ban_answer(
+                command,
+                args,
+                &message
+                    .text()
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .skip(1)
+                    .collect::<Vec<&str>>(),
+                author,
+                conn,
+            ),
+            "modlog" => modlog_answer(args.first().copied(), author, conn),
+            "setlimit" => setlimit_answer(args, author, conn),
+            "jobs" => jobs_answer(&author.language, conn),
+            _ => user_answer(args, author, conn),
+        }
+    };
+
+    reply(bot, message, text).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(parse_duration("30m"), Some(ChronoDuration::minutes(30)));
+        assert_eq!(parse_duration("12h"), Some(ChronoDuration::hours(12)));
+        assert_eq!(parse_duration("7d"), Some(ChronoDuration::days(7)));
+        assert_eq!(parse_duration("2w"), Some(ChronoDuration::weeks(2)));
+    }
+
+    #[test]
+    fn parse_duration_invalid() {
+        for duration in ["", "m", "0d", "-1d", "10", "10s", "1.5h", "d7"] {
+            assert_eq!(parse_duration(duration), None, "{duration}");
+        }
+    }
+
+    #[test]
+    fn parse_duration_non_ascii() {
+        for duration in ["спам", "سبام", "5д", "🤖", "é"] {
+            assert_eq!(parse_duration(duration), None, "{duration}");
+        }
+    }
+
+    #[test]
+    fn parse_duration_overflow() {
+        assert_eq!(parse_duration(&format!("{}w", i64::MAX)), None);
+        assert_eq!(parse_duration("99999999999999999999m"), None);
+        assert!(
+            parse_duration("100000000w").is_none_or(|duration| offset::Utc::now()
+                .naive_utc()
+                .checked_add_signed(duration)
+                .is_none())
+        );
+    }
+}
@@ -18,17 +18,22 @@
 
 use crate::models::Users;
 use crate::{
+    admin,
+    callback::CallbackData,
+    errors::{RpgError, RpgResult},
     keyboards,
-    models::{Config, NewSourceCode, SourceCode},
+    metrics::{self, LogDbError},
+    models::{CachedResult, Config, NewEvent, NewSnippet, NewSourceCode, Snippet, SourceCode},
+    queue::{Cancellation, Permit, Ticket},
     rpg,
-    rpg_db::{self, languages_ctx},
+    rpg_db::{self, languages_ctx, DbConnection, DbPool, PooledDbConnection},
+    settings::SETTINGS,
 };
 use chrono::offset;
-use diesel::SqliteConnection;
-use futures::try_join;
+use futures::{try_join, TryFutureExt};
 use json_gettext::get_text;
 use std::collections::HashMap;
-use std::error::Error;
+use std::time::{Duration, Instant};
 use strfmt::strfmt;
 use teloxide::utils::command::parse_command;
 use teloxide::{
@@ -36,9 +41,22 @@ use teloxide::{
     requests::Requester,
     types::{InlineKeyboardMarkup, ParseMode, User},
     utils::command::BotCommand,
-    RequestError,
 };
 
+/// Commands that all users can use
+const USER_COMMANDS: [&str; 12] = [
+    "run", "share", "help", "start", "language", "info", "get", "history", "save", "snippets",
+    "find", "delete",
+];
+/// Number of source codes in every history page
+const HISTORY_PAGE_SIZE: i64 = 5;
+/// Number of snippets in every snippets page
+const SNIPPETS_PAGE_SIZE: i64 = 5;
+/// Maximum number of snippets in the search results
+const SEARCH_RESULTS_MAXIMUM: i64 = 10;
+/// Maximum length of the snippet name
+const SNIPPET_NAME_MAXIMUM: usize = 32;
+
 #[derive(BotCommand)]
 pub enum Command {
     #[command(parse_with = "split")]
@@ -112,14 +130,30 @@ impl From<(&NewSourceCode, &str)> for Command {
     }
 }
 
+/// The bot username, fetched once at startup and passed to the handlers with the dependencies
+#[derive(Clone)]
+pub struct BotUsername(pub String);
+
 /// Returns bot username
-pub async fn bot_username(bot: &AutoSend<Bot>) -> String {
-    bot.get_me()
-        .await
-        .unwrap()
-        .user
-        .username
-        .expect("Bots must have usernames")
+pub async fn bot_username(bot: &AutoSend<Bot>) -> RpgResult<BotUsername> {
+    Ok(BotUsername(
+        bot.get_me()
+            .await?
+            .user
+            .username
+            .expect("Bots must have usernames"),
+    ))
+}
+
+/// Returns the error of the buttons that don't belong to the message keyboard anymore
+fn button_expired_error(language: &str) -> RpgError {
+    let ctx = languages_ctx();
+    RpgError::Validation(
+        get_text!(ctx, language, "BUTTON_EXPIRED")
+            .unwrap()
+            .to_string()
+            + " ⌛",
+    )
 }
 
 /// Returns wait message of command (Run and Share) else return `None`
@@ -186,10 +220,11 @@ async fn replay_wait_message(
     message_id: i32,
     command: &Command,
     language: &str,
-) -> Result<Message, Box<dyn Error + Send + Sync>> {
+) -> RpgResult<Message> {
     Ok(bot
         .send_message(chat_id, get_wait_message(command, language).unwrap())
         .reply_to_message_id(message_id)
+        .reply_markup(keyboards::cancel_keyboard(language))
         .send()
         .await?)
 }
@@ -199,14 +234,45 @@ async fn send_wait_message(
     chat_id: i64,
     command: &Command,
     language: &str,
-) -> Result<Message, Box<dyn Error + Send + Sync>> {
+) -> RpgResult<Message> {
     Ok(bot
         .send_message(chat_id, get_wait_message(command, language).unwrap())
+        .reply_markup(keyboards::cancel_keyboard(language))
         .send()
         .await?)
 }
 
-fn delay_error_message(author: &Users, is_command: bool, conn: &mut SqliteConnection) -> String {
+/// Edit the wait message with the position of the request in the queue
+async fn queue_position_answer(
+    bot: &AutoSend<Bot>,
+    message: &Message,
+    command: &Command,
+    position: usize,
+    language: &str,
+) {
+    let ctx = languages_ctx();
+    let mut vars: HashMap<String, String> = HashMap::new();
+    vars.insert("position".into(), position.to_string());
+    let text: String = format!(
+        "{}\n\n{}",
+        get_wait_message(command, language).unwrap(),
+        strfmt(
+            &get_text!(ctx, language, "QUEUE_POSITION")
+                .unwrap()
+                .to_string(),
+            &vars
+        )
+        .unwrap()
+    );
+    bot.edit_message_text(message.chat.id, message.id, text)
+        .reply_markup(keyboards::cancel_keyboard(language))
+        .send()
+        .await
+        .log_on_error()
+        .await;
+}
+
+fn delay_error_message(author: &Users, is_command: bool, conn: &mut DbConnection) -> String {
     let mut vars: HashMap<String, String> = HashMap::new();
     let ctx = languages_ctx();
 
@@ -221,17 +287,9 @@ fn delay_error_message(author: &Users, is_command: bool, conn: &mut SqliteConnec
         // `can_send_command` and `can_click_button` functions will return `true`.
         .timestamp()
             + if is_command {
-                // default value is 15
-                Config::get_or_add("command_delay", "15", conn)
-                    .value
-                    .parse::<i64>()
-                    .expect("`command_delay` config should be integer")
+                author.current_command_delay(conn)
             } else {
-                // default value is 2
-                Config::get_or_add("button_delay", "2", conn)
-                    .value
-                    .parse::<i64>()
-                    .expect("`button_delay` config should be integer")
+                author.current_button_delay(conn)
             })
             - (offset::Utc::now().timestamp()))
         .to_string(),
@@ -253,7 +311,29 @@ fn delay_error_message(author: &Users, is_command: bool, conn: &mut SqliteConnec
     .unwrap()
 }
 
-fn attempt_error_message(author: &Users) -> String {
+/// Returns the time until the attempts reset as `HH:MM:SS`, or never if the attempts will not reset
+fn resets_in_text(author: &Users, conn: &mut DbConnection) -> String {
+    let ctx = languages_ctx();
+    match author.attempts_reset_in(conn) {
+        Some(seconds) => format!(
+            "{:02}:{:02}:{:02}",
+            seconds / (60 * 60),
+            (seconds / 60) % 60,
+            seconds % 60
+        ),
+        None if Config::integer("attempts_window", conn) > 0 => {
+            // the attempts window doesn't start yet
+            get_text!(ctx, &author.language, "NOT_STARTED")
+                .unwrap()
+                .to_string()
+        }
+        None => get_text!(ctx, &author.language, "NEVER")
+            .unwrap()
+            .to_string(),
+    }
+}
+
+fn attempt_error_message(author: &Users, conn: &mut DbConnection) -> String {
     let mut vars: HashMap<String, String> = HashMap::new();
     let ctx = languages_ctx();
 
@@ -261,6 +341,7 @@ fn attempt_error_message(author: &Users) -> String {
         "attempts_maximum".into(),
         author.attempts_maximum.to_string(),
     );
+    vars.insert("resets_in".into(), resets_in_text(author, conn));
     strfmt(
         &get_text!(ctx, &author.language, "EXCEEDED_ATTEMPTS_MESSAGE")
             .unwrap()
@@ -270,7 +351,16 @@ fn attempt_error_message(author: &Users) -> String {
     .unwrap()
 }
 
-/// Share and run, and make attempt for user
+/// Returns the text limited to the telegram message length
+fn limit_text(text: &str) -> String {
+    // For text messages, the actual UTF-8 text of the message, 0-4096 characters
+    // https://core.telegram.org/bots/api#message
+    text.chars().take(4096).collect::<String>()
+}
+
+/// Share and run, and make attempt for user. The connection is released while the request waits
+/// in the queue and for the Rust playground, and taken again from the pool after that
+#[allow(clippy::too_many_arguments)]
 async fn share_run_answer(
     bot: &AutoSend<Bot>,
     command: &Command,
@@ -278,65 +368,148 @@ async fn share_run_answer(
     message: &Message,
     author: &mut Users,
     code: &NewSourceCode,
-    conn: &mut SqliteConnection,
-) -> Result<(), Box<dyn Error + Send + Sync>> {
-    let output: Result<String, String> = if command.name() == "run" {
-        rpg::run(&code.into()).await
+    use_cache: bool,
+    pool: &DbPool,
+    mut connection: PooledDbConnection,
+) -> RpgResult<()> {
+    let cached: Option<CachedResult> = if use_cache {
+        CachedResult::get(&code.hash, command.name(), &mut connection)
     } else {
-        rpg::share(&code.into()).await
+        None
+    };
+    let is_cached: bool = cached.is_some();
+
+    let (output, duration, mut connection): (
+        RpgResult<String>,
+        Option<Duration>,
+        PooledDbConnection,
+    ) = match cached {
+        Some(cached) => (Ok(cached.output), None, connection),
+        None => {
+            drop(connection);
+            let language: &str = &author.language;
+            let mut cancellation = Cancellation::new(message.chat.id, message.id, author.id);
+            let request = async {
+                let _permit: Permit = Ticket::new(author.id)
+                    .wait(|position| {
+                        queue_position_answer(bot, message, command, position, language)
+                    })
+                    .await;
+                let start: Instant = Instant::now();
+                let output: RpgResult<String> = if command.name() == "run" {
+                    rpg::run(&code.into()).await
+                } else {
+                    rpg::share(&code.into()).await
+                };
+                (output, Some(start.elapsed()))
+            };
+            let (output, duration) = tokio::select! {
+                result = request => result,
+                _ = cancellation.cancelled() => {
+                    // the request is dropped (removed from the queue or aborted),
+                    // and the attempt is not made
+                    metrics::PLAYGROUND_REQUESTS
+                        .with_label_values(&[command.name(), "cancelled"])
+                        .inc();
+                    return Ok(());
+                }
+            };
+            (output, duration, rpg_db::connection(pool).await?)
+        }
     };
+    let conn: &mut DbConnection = &mut connection;
+    metrics::PLAYGROUND_REQUESTS
+        .with_label_values(&[
+            command.name(),
+            match (is_cached, &output) {
+                (true, _) => "cached",
+                (false, Ok(_)) => "success",
+                (false, Err(RpgError::PlaygroundUnavailable)) => "unavailable",
+                (false, Err(_)) => "failure",
+            },
+        ])
+        .inc();
+    if let Some(duration) = duration {
+        metrics::PLAYGROUND_LATENCY
+            .with_label_values(&[command.name()])
+            .observe(duration.as_secs_f64());
+    }
+    NewEvent::run_share(
+        author,
+        message.chat.id,
+        code,
+        command.name(),
+        duration.map(|duration| duration.as_millis() as i32),
+        &output,
+    )
+    .save(conn)
+    .log_db_error();
+
+    if let (false, Ok(output)) = (is_cached, &output) {
+        CachedResult::save(&code.hash, command.name(), output, conn).log_db_error();
+    }
+
+    if let Err(err) = &output {
+        // the invalid source codes are not logged, see `RpgError::log`
+        err.log();
+    }
 
-    if output.is_ok() {
-        code.save(conn)?;
+    // The saved source code may be a previously saved one (same source code and options)
+    let saved_code: String = if output.is_ok() {
+        code.save(conn)?.code
+    } else {
+        code.code.clone()
     };
 
     let (keyboard, output): (InlineKeyboardMarkup, String) = if command.name() == "run" {
         (
             keyboards::view_share_keyboard(
-                code.code.clone(),
+                &saved_code,
                 already_use_keyboard,
                 output.is_ok(),
                 &author.language,
             ),
-            match output {
-                Ok(output) => output,
-                Err(output) => output,
+            match &output {
+                Ok(output) => output.clone(),
+                Err(err) => err.user_message(&author.language),
             },
         )
     } else {
         (
             keyboards::view_run_keyboard(
-                code.code.clone(),
+                &saved_code,
                 already_use_keyboard,
                 output.is_ok(),
                 &author.language,
             ),
-            match output {
-                Ok(output) => output,
-                Err(output) => output,
+            match &output {
+                Ok(output) => output.clone(),
+                Err(err) => err.user_message(&author.language),
             },
         )
     };
-    author.make_attempt(conn).log_on_error().await;
-    bot.edit_message_text(
-        // For text messages, the actual UTF-8 text of the message, 0-4096 characters
-        // https://core.telegram.org/bots/api#message
-        message.chat.id,
-        message.id,
-        output
-            .chars()
-            .take(if output.chars().count() > 4096 {
-                4096
-            } else {
-                output.chars().count()
-            })
-            .collect::<String>(),
-    )
-    .reply_markup(keyboard)
-    .send()
-    .await
-    .log_on_error()
-    .await;
+
+    let (keyboard, output): (InlineKeyboardMarkup, String) = if is_cached {
+        // cached results don't make attempt
+        let ctx = languages_ctx();
+        (
+            keyboards::force_keyboard(keyboard, command.name(), &saved_code, &author.language),
+            format!(
+                "{} ♻️\n\n{}",
+                get_text!(ctx, &author.language, "CACHED_RESULT").unwrap(),
+                output
+            ),
+        )
+    } else {
+        author.make_attempt(conn).log_db_error();
+        (keyboard, output)
+    };
+    bot.edit_message_text(message.chat.id, message.id, limit_text(&output))
+        .reply_markup(keyboard)
+        .send()
+        .await
+        .log_on_error()
+        .await;
 
     Ok(())
 }
@@ -346,43 +519,30 @@ pub async fn share_run_answer_message(
     bot: &AutoSend<Bot>,
     message: &Message,
     command: &Command,
-    author: &Users,
-    conn: &mut SqliteConnection,
-) -> Result<(), Box<dyn Error + Send + Sync>> {
-    SourceCode::filter_source_codes(conn).unwrap();
-    let source_code_message: &Message = message.reply_to_message().unwrap();
-    if let Some(source_code) = source_code_message.text() {
+    author: &mut Users,
+    pool: &DbPool,
+    mut connection: PooledDbConnection,
+) -> RpgResult<()> {
+    if let Some(source_code) = message.reply_to_message().and_then(Message::text) {
         if let Some((version, mode, edition)) = command.args() {
             let code: rpg::Code = rpg::Code::new(source_code, version, mode, edition);
-            if let Err(err) = code.is_valid() {
-                bot.send_message(message.chat.id, err)
-                    .reply_to_message_id(message.id)
-                    .send()
-                    .await
-                    .log_on_error()
-                    .await;
-            } else {
-                let reply_message: Message = replay_wait_message(
-                    bot,
-                    message.chat.id,
-                    message.id,
-                    command,
-                    &author.language,
-                )
-                .await?;
-                share_run_answer(
-                    bot,
-                    command,
-                    false,
-                    &reply_message,
-                    &mut rpg_db::get_user(conn, message.from().unwrap()).unwrap(),
-                    &NewSourceCode::new(conn, &code, author)?,
-                    conn,
-                )
-                .await
-                .log_on_error()
-                .await;
-            }
+            code.is_valid()?;
+            let reply_message: Message =
+                replay_wait_message(bot, message.chat.id, message.id, command, &author.language)
+                    .await?;
+            let source: NewSourceCode = NewSourceCode::new(&mut connection, &code, author)?;
+            share_run_answer(
+                bot,
+                command,
+                false,
+                &reply_message,
+                author,
+                &source,
+                true,
+                pool,
+                connection,
+            )
+            .await?;
         }
     } else {
         let ctx = languages_ctx();
@@ -401,6 +561,7 @@ pub async fn share_run_answer_message(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn share_run_answer_cllback(
     bot: &AutoSend<Bot>,
     chat_id: i64,
@@ -408,25 +569,24 @@ pub async fn share_run_answer_cllback(
     code: NewSourceCode,
     author: &User,
     language: &str,
-    conn: &mut SqliteConnection,
-) -> Result<(), RequestError> {
+    use_cache: bool,
+    pool: &DbPool,
+    mut connection: PooledDbConnection,
+) -> RpgResult<()> {
     let message: Message =
-        send_wait_message(bot, chat_id, &Command::from((&code, command)), language)
-            .await
-            .unwrap();
+        send_wait_message(bot, chat_id, &Command::from((&code, command)), language).await?;
     share_run_answer(
         bot,
         &Command::from((&code, command)),
         true,
         &message,
-        &mut rpg_db::get_user(conn, author).unwrap(),
+        &mut rpg_db::get_user(&mut connection, author)?,
         &code,
-        conn,
+        use_cache,
+        pool,
+        connection,
     )
     .await
-    .log_on_error()
-    .await;
-    Ok(())
 }
 
 /// Returns the code args from command args
@@ -454,7 +614,7 @@ fn get_args(args: Vec<&str>) -> Vec<String> {
 }
 
 /// returns None that mean the message is deleted else message content
-fn get_source_code(code: &str, conn: &mut SqliteConnection) -> Option<SourceCode> {
+fn get_source_code(code: &str, conn: &mut DbConnection) -> Option<SourceCode> {
     SourceCode::get_by_code(code, conn).ok()
 }
 
@@ -472,24 +632,35 @@ async fn cannot_reached_answer(bot: &AutoSend<Bot>, query_id: &str, language: &s
         .await;
 }
 
+async fn not_author_answer(bot: &AutoSend<Bot>, query_id: &str, language: &str) {
+    let ctx = languages_ctx();
+    bot.answer_callback_query(query_id)
+        .text(get_text!(ctx, language, "NOT_AUTHOR").unwrap().to_string() + " 🤨")
+        .send()
+        .await
+        .log_on_error()
+        .await;
+}
+
 async fn run_share_callback(
     bot: &AutoSend<Bot>,
     callback_query: &CallbackQuery,
     command: &str,
     code: String,
     language: &str,
-    conn: &mut SqliteConnection,
+    pool: &DbPool,
+    mut connection: PooledDbConnection,
 ) {
     // share and run commands need source code
     // if get_source_code returns None that mean the source code message is deleted
-    if let Some(source_code) = get_source_code(&code, conn) {
+    if let Some(source_code) = get_source_code(&code, &mut connection) {
         let message: Message = callback_query.clone().message.unwrap();
         let keyboard: InlineKeyboardMarkup = if command == "share" {
             keyboards::view_share_keyboard(code, true, true, language)
         } else {
             keyboards::view_run_keyboard(code, true, true, language)
         };
-        try_join!(
+        if let Err(err) = try_join!(
             share_run_answer_cllback(
                 bot,
                 message.chat.id,
@@ -497,14 +668,91 @@ async fn run_share_callback(
                 source_code.into(),
                 &callback_query.from,
                 language,
-                conn
+                true,
+                pool,
+                connection
             ),
             bot.edit_message_reply_markup(message.chat.id, message.id)
                 .reply_markup(keyboard)
                 .send()
+                .err_into()
+        ) {
+            err.report_callback(bot, &callback_query.id, language).await;
+        }
+    } else {
+        cannot_reached_answer(bot, &callback_query.id, language).await;
+    }
+}
+
+/// Cancel the run/share of the wait message, the wait message is edited here because the
+/// cancelled request doesn't edit it
+async fn cancel_callback(bot: &AutoSend<Bot>, callback_query: &CallbackQuery, author: &Users) {
+    let ctx = languages_ctx();
+    let message: Message = callback_query.clone().message.unwrap();
+    let text_key: &str = if Cancellation::cancel(message.chat.id, message.id, author.id) {
+        bot.edit_message_text(
+            message.chat.id,
+            message.id,
+            get_text!(ctx, &author.language, "CANCELLED")
+                .unwrap()
+                .to_string()
+                + " ✖️",
+        )
+        .send()
+        .await
+        .log_on_error()
+        .await;
+        "CANCELLED"
+    } else {
+        "CANNOT_CANCEL"
+    };
+    bot.answer_callback_query(&callback_query.id)
+        .text(
+            get_text!(ctx, &author.language, text_key)
+                .unwrap()
+                .to_string(),
         )
+        .send()
+        .await
         .log_on_error()
         .await;
+}
+
+/// Run/Share the source code again without the cached result
+async fn force_callback(
+    bot: &AutoSend<Bot>,
+    callback_query: &CallbackQuery,
+    command: &str,
+    code: &str,
+    language: &str,
+    pool: &DbPool,
+    mut connection: PooledDbConnection,
+) {
+    if let Some(source_code) = get_source_code(code, &mut connection) {
+        let message: Message = callback_query.clone().message.unwrap();
+        // remove the force button, the new result will be sent in a new message
+        let mut keyboard: InlineKeyboardMarkup =
+            message.reply_markup().cloned().unwrap_or_default();
+        keyboard.inline_keyboard.pop();
+        if let Err(err) = try_join!(
+            share_run_answer_cllback(
+                bot,
+                message.chat.id,
+                command,
+                source_code.into(),
+                &callback_query.from,
+                language,
+                false,
+                pool,
+                connection
+            ),
+            bot.edit_message_reply_markup(message.chat.id, message.id)
+                .reply_markup(keyboard)
+                .send()
+                .err_into()
+        ) {
+            err.report_callback(bot, &callback_query.id, language).await;
+        }
     } else {
         cannot_reached_answer(bot, &callback_query.id, language).await;
     }
@@ -516,10 +764,40 @@ async fn update_options(
     code: &str,
     option_name: &str,
     option_value: &str,
-    language: &str,
-    conn: &mut SqliteConnection,
+    author: &Users,
+    conn: &mut DbConnection,
 ) {
+    let language: &str = &author.language;
     if let Ok(mut source) = SourceCode::get_by_code(code, conn) {
+        if !source.is_author(author) {
+            // only the author can change the options of the saved source code
+            return not_author_answer(bot, &callback_query.id, language).await;
+        }
+        let message: &Message = match callback_query.message.as_ref() {
+            Some(message) => message,
+            None => {
+                return button_expired_error(language)
+                    .report_callback(bot, &callback_query.id, language)
+                    .await
+            }
+        };
+        // the run/share button is the first button of the fifth row of the options keyboard
+        let old_keybord: &InlineKeyboardMarkup = match message.reply_markup().filter(|keyboard| {
+            keyboard
+                .inline_keyboard
+                .get(4)
+                .is_some_and(|row| !row.is_empty())
+        }) {
+            Some(keyboard) => keyboard,
+            None => {
+                return button_expired_error(language)
+                    .report_callback(bot, &callback_query.id, language)
+                    .await
+            }
+        };
+        if let Err(err) = source.update_by_name(option_name, option_value, conn) {
+            return err.report_callback(bot, &callback_query.id, language).await;
+        }
         let mut vars: HashMap<String, String> = HashMap::new();
         let ctx = languages_ctx();
 
@@ -531,8 +809,6 @@ async fn update_options(
         );
         vars.insert("option_value".into(), option_value.to_string());
 
-        let message: Message = callback_query.clone().message.unwrap();
-        let old_keybord: &InlineKeyboardMarkup = message.reply_markup().unwrap();
         let answer = bot
             .answer_callback_query(&callback_query.id)
             .text(
@@ -544,16 +820,11 @@ async fn update_options(
             )
             .send();
 
-        source
-            .update_by_name(option_name, option_value, conn)
-            .log_on_error()
-            .await;
-
         let keyboard: InlineKeyboardMarkup =
             if old_keybord.inline_keyboard[4][0].text.contains("Run") {
-                keyboards::run_keyboard(source, language)
+                keyboards::run_keyboard(source, true, language)
             } else {
-                keyboards::share_keyboard(source, language)
+                keyboards::share_keyboard(source, true, language)
             };
 
         if &keyboard != old_keybord {
@@ -581,16 +852,15 @@ async fn change_langauge(
     chat_id: i64,
     new_language: &str,
     query_id: &str,
-    conn: &mut SqliteConnection,
+    conn: &mut DbConnection,
 ) {
     let ctx = languages_ctx();
-    let new_language = new_language.replace("_", " ");
 
     // if new language same old one
     if new_language == author.language {
         bot.answer_callback_query(query_id)
             .text(
-                get_text!(ctx, &new_language, "ALREADY_CURRENT_LANGUAGE")
+                get_text!(ctx, new_language, "ALREADY_CURRENT_LANGUAGE")
                     .unwrap()
                     .to_string()
                     + " 🤨",
@@ -600,19 +870,16 @@ async fn change_langauge(
             .log_on_error()
             .await;
     } else {
-        author
-            .update_language(&new_language, conn)
-            .log_on_error()
-            .await;
+        author.update_language(new_language, conn).log_db_error();
         bot.edit_message_text(
             chat_id,
             message_id,
-            get_text!(ctx, &new_language, "CHANGE_LANGUAGE_SUCCESSFULLY")
+            get_text!(ctx, new_language, "CHANGE_LANGUAGE_SUCCESSFULLY")
                 .unwrap()
                 .to_string()
                 + " 🤖",
         )
-        .reply_markup(keyboards::add_lang_keyboard(&new_language))
+        .reply_markup(keyboards::add_lang_keyboard(new_language))
         .send()
         .await
         .log_on_error()
@@ -620,19 +887,17 @@ async fn change_langauge(
     }
 }
 
-pub fn info_text(author: &Users, conn: &mut SqliteConnection) -> String {
+pub fn info_text(author: &Users, conn: &mut DbConnection) -> String {
     let ctx = languages_ctx();
     let mut vars: HashMap<String, String> = HashMap::new();
     vars.insert("full_name".into(), author.telegram_fullname.clone());
     vars.insert(
         "command_delay".into(),
-        // default value is 15
-        Config::get_or_add("command_delay", "15", conn).value,
+        author.current_command_delay(conn).to_string(),
     );
     vars.insert(
         "button_delay".to_string(),
-        // default value is 2
-        Config::get_or_add("button_delay", "2", conn).value,
+        author.current_button_delay(conn).to_string(),
     );
     vars.insert(
         "attempts_maximum".into(),
@@ -643,6 +908,7 @@ pub fn info_text(author: &Users, conn: &mut SqliteConnection) -> String {
         "attempts_have".into(),
         (author.attempts_maximum - author.attempts).to_string(),
     );
+    vars.insert("resets_in".into(), resets_in_text(author, conn));
 
     strfmt(
         &get_text!(ctx, &author.language, "INFO_MESSAGE")
@@ -653,66 +919,773 @@ pub fn info_text(author: &Users, conn: &mut SqliteConnection) -> String {
     .unwrap()
 }
 
-/// Run and Share command handler
-pub async fn command_handler(
+/// Returns source code message, with its author, options and created date
+pub fn source_text(source: &SourceCode, language: &str, conn: &mut DbConnection) -> String {
+    let ctx = languages_ctx();
+    let mut vars: HashMap<String, String> = HashMap::new();
+    vars.insert("code".into(), source.code.clone());
+    vars.insert(
+        "author".into(),
+        source
+            .author(conn)
+            .map(|author| author.mention())
+            .unwrap_or_else(|_| "-".into()),
+    );
+    vars.insert("version".into(), source.version.clone());
+    vars.insert("mode".into(), source.mode.clone());
+    vars.insert("edition".into(), source.edition.clone());
+    vars.insert(
+        "created_at".into(),
+        source.created_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+    );
+    vars.insert(
+        "visibility".into(),
+        get_text!(
+            ctx,
+            language,
+            if source.is_public {
+                "PUBLIC"
+            } else {
+                "PRIVATE"
+            }
+        )
+        .unwrap()
+        .to_string(),
+    );
+    vars.insert("source_code".into(), source.source_code.clone());
+
+    limit_text(
+        &strfmt(
+            &get_text!(ctx, language, "GET_MESSAGE").unwrap().to_string(),
+            &vars,
+        )
+        .unwrap(),
+    )
+}
+
+/// Returns the source code message and its keyboard if the user can see it, else the error message
+fn source_answer(
+    code: &str,
+    author: &Users,
+    conn: &mut DbConnection,
+) -> (String, Option<InlineKeyboardMarkup>) {
+    let ctx = languages_ctx();
+    match SourceCode::get_by_code(code, conn) {
+        Ok(source) if source.can_view(author) => (
+            source_text(&source, &author.language, conn),
+            Some(keyboards::get_keyboard(
+                &source,
+                source.is_author(author),
+                &author.language,
+            )),
+        ),
+        Ok(_) => (
+            get_text!(ctx, &author.language, "PRIVATE_SOURCE")
+                .unwrap()
+                .to_string()
+                + " 🔒",
+            None,
+        ),
+        Err(_) => (
+            get_text!(ctx, &author.language, "SOURCES_CANNOT_REACHED")
+                .unwrap()
+                .to_string(),
+            None,
+        ),
+    }
+}
+
+/// Send source code message in reply to `message_id`
+async fn send_source_answer(
+    bot: &AutoSend<Bot>,
+    chat_id: i64,
+    message_id: i32,
+    (text, keyboard): (String, Option<InlineKeyboardMarkup>),
+) {
+    let request = bot
+        .send_message(chat_id, text)
+        .reply_to_message_id(message_id);
+    if let Some(keyboard) = keyboard {
+        request.reply_markup(keyboard)
+    } else {
+        request
+    }
+    .send()
+    .await
+    .log_on_error()
+    .await;
+}
+
+/// Get command handler, send the source code of the given code with run/share keyboard
+async fn get_handler(
     bot: &AutoSend<Bot>,
     message: &Message,
-    command: &Command,
+    code: Option<&str>,
     author: &Users,
-    conn: &mut SqliteConnection,
-) -> Result<(), Box<dyn Error + Send + Sync>> {
-    // Share and Run command need reply message
-    if message.reply_to_message().is_some() {
-        share_run_answer_message(bot, message, command, author, conn)
-            .await
-            .log_on_error()
-            .await;
-    } else {
-        let ctx = languages_ctx();
-        // If there is no reply message
-        bot.send_message(
-            message.chat.id,
-            get_text!(ctx, &author.language, "REPLY_MESSAGE")
+    conn: &mut DbConnection,
+) {
+    let ctx = languages_ctx();
+    let answer: (String, Option<InlineKeyboardMarkup>) = match code {
+        Some(code) => source_answer(code, author, conn),
+        None => (
+            get_text!(ctx, &author.language, "GET_HELP")
                 .unwrap()
                 .to_string(),
-        )
-        .reply_to_message_id(message.id)
-        .send()
-        .await
-        .log_on_error()
-        .await;
+            None,
+        ),
     };
-
-    Ok(())
+    send_source_answer(bot, message.chat.id, message.id, answer).await;
 }
 
-pub async fn message_text_handler(message: Message, bot: AutoSend<Bot>) {
-    if let Some(text) = message.text() {
-        let conn: &mut SqliteConnection = &mut rpg_db::establish_connection();
+/// Send the source code of the clicked history button with its run/share keyboard
+async fn get_callback(
+    bot: &AutoSend<Bot>,
+    callback_query: &CallbackQuery,
+    code: &str,
+    author: &Users,
+    conn: &mut DbConnection,
+) {
+    // unwrap here because every callback query have message 🙂
+    let message: Message = callback_query.clone().message.unwrap();
+    let answer: (String, Option<InlineKeyboardMarkup>) = source_answer(code, author, conn);
+    if answer.1.is_some() {
+        send_source_answer(bot, message.chat.id, message.id, answer).await;
+        bot.answer_callback_query(&callback_query.id)
+            .send()
+            .await
+            .log_on_error()
+            .await;
+    } else {
+        // Can't see the source code, answer with the error message
+        bot.answer_callback_query(&callback_query.id)
+            .text(answer.0)
+            .send()
+            .await
+            .log_on_error()
+            .await;
+    }
+}
 
-        let mut author: Users = rpg_db::get_user(conn, message.from().unwrap()).unwrap();
+/// Returns history message and keyboard of the given page, `None` if the page is not exist
+fn history_answer(
+    author: &Users,
+    page: i64,
+    conn: &mut DbConnection,
+) -> Option<(String, InlineKeyboardMarkup)> {
+    let ctx = languages_ctx();
+    let count: i64 = author.source_codes_count(conn).ok()?;
+    if count == 0 {
+        return Some((
+            get_text!(ctx, &author.language, "HISTORY_EMPTY")
+                .unwrap()
+                .to_string()
+                + " 🦀",
+            InlineKeyboardMarkup::default(),
+        ));
+    }
+    let pages: i64 = (count + HISTORY_PAGE_SIZE - 1) / HISTORY_PAGE_SIZE;
+    if !(0..pages).contains(&page) {
+        return None;
+    }
+    let sources: Vec<SourceCode> = author
+        .source_codes_page(page, HISTORY_PAGE_SIZE, conn)
+        .ok()?;
 
-        if let Some((command, args)) = parse_command(
-            &text.to_ascii_lowercase(),
-            bot_username(&bot).await.to_ascii_lowercase(),
-        ) {
+    let mut vars: HashMap<String, String> = HashMap::new();
+    vars.insert("count".into(), count.to_string());
+    vars.insert(
+        "sources".into(),
+        sources
+            .iter()
+            .map(|source| {
+                format!(
+                    "{} | {} {} {} | {}\n{}",
+                    source.code,
+                    source.version,
+                    source.mode,
+                    source.edition,
+                    source.created_at.format("%Y-%m-%d %H:%M"),
+                    keyboards::source_preview(&source.source_code)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n\n"),
+    );
+
+    Some((
+        strfmt(
+            &get_text!(ctx, &author.language, "HISTORY_MESSAGE")
+                .unwrap()
+                .to_string(),
+            &vars,
+        )
+        .unwrap(),
+        keyboards::history_keyboard(&sources, author.id, page, pages),
+    ))
+}
+
+/// History command handler, send the first page of the user source codes
+async fn history_handler(
+    bot: &AutoSend<Bot>,
+    message: &Message,
+    author: &Users,
+    conn: &mut DbConnection,
+) {
+    if let Some((text, keyboard)) = history_answer(author, 0, conn) {
+        bot.send_message(message.chat.id, text)
+            .reply_to_message_id(message.id)
+            .reply_markup(keyboard)
+            .send()
+            .await
+            .log_on_error()
+            .await;
+    }
+}
+
+/// Move the history message to another page, only the history owner can do it
+async fn history_callback(
+    bot: &AutoSend<Bot>,
+    callback_query: &CallbackQuery,
+    user_id: i32,
+    page: i64,
+    author: &Users,
+    conn: &mut DbConnection,
+) {
+    let ctx = languages_ctx();
+    if user_id != author.id {
+        bot.answer_callback_query(&callback_query.id)
+            .text(
+                get_text!(ctx, &author.language, "NOT_YOUR_HISTORY")
+                    .unwrap()
+                    .to_string()
+                    + " 🤨",
+            )
+            .send()
+            .await
+            .log_on_error()
+            .await;
+    } else if let Some((text, keyboard)) = history_answer(author, page, conn) {
+        // unwrap here because every callback query have message 🙂
+        let message: Message = callback_query.clone().message.unwrap();
+        try_join!(
+            bot.answer_callback_query(&callback_query.id).send(),
+            bot.edit_message_text(message.chat.id, message.id, text)
+                .reply_markup(keyboard)
+                .send()
+        )
+        .log_on_error()
+        .await;
+    } else {
+        cannot_reached_answer(bot, &callback_query.id, &author.language).await;
+    }
+}
+
+/// Toggle source code visibility, only the author can do it
+async fn visibility_callback(
+    bot: &AutoSend<Bot>,
+    callback_query: &CallbackQuery,
+    code: &str,
+    author: &Users,
+    conn: &mut DbConnection,
+) {
+    let ctx = languages_ctx();
+    match SourceCode::get_by_code(code, conn) {
+        Ok(mut source) if source.is_author(author) => {
+            source.toggle_visibility(conn).log_db_error();
+
+            let mut vars: HashMap<String, String> = HashMap::new();
+            vars.insert(
+                "visibility".into(),
+                get_text!(
+                    ctx,
+                    &author.language,
+                    if source.is_public {
+                        "PUBLIC"
+                    } else {
+                        "PRIVATE"
+                    }
+                )
+                .unwrap()
+                .to_string(),
+            );
+            // unwrap here because every callback query have message 🙂
+            let message: Message = callback_query.clone().message.unwrap();
+            try_join!(
+                bot.answer_callback_query(&callback_query.id)
+                    .text(
+                        strfmt(
+                            &get_text!(ctx, &author.language, "VISIBILITY_MESSAGE")
+                                .unwrap()
+                                .to_string(),
+                            &vars,
+                        )
+                        .unwrap(),
+                    )
+                    .send(),
+                bot.edit_message_text(
+                    message.chat.id,
+                    message.id,
+                    source_text(&source, &author.language, conn),
+                )
+                .reply_markup(keyboards::get_keyboard(&source, true, &author.language))
+                .send()
+            )
+            .log_on_error()
+            .await;
+        }
+        Ok(_) => not_author_answer(bot, &callback_query.id, &author.language).await,
+        Err(_) => cannot_reached_answer(bot, &callback_query.id, &author.language).await,
+    }
+}
+
+/// Returns snippet line, with its name, tags, code and first line of the source code
+fn snippet_line(snippet: &Snippet, source: &SourceCode) -> String {
+    format!(
+        "{}{} | {}\n{}",
+        snippet.name,
+        snippet
+            .tags()
+            .iter()
+            .map(|tag| format!(" #{}", tag))
+            .collect::<String>(),
+        source.code,
+        keyboards::source_preview(&source.source_code)
+    )
+}
+
+/// Save command handler, save the replied source code as a named snippet (snippets never expire)
+async fn save_handler(
+    bot: &AutoSend<Bot>,
+    message: &Message,
+    args: &[&str],
+    author: &Users,
+    conn: &mut DbConnection,
+) {
+    let ctx = languages_ctx();
+    let mut vars: HashMap<String, String> = HashMap::new();
+    let (text, keyboard): (String, Option<InlineKeyboardMarkup>) = match (
+        message.reply_to_message().map(|reply| reply.text()),
+        args.first(),
+    ) {
+        (None, _) => (
+            get_text!(ctx, &author.language, "REPLY_MESSAGE")
+                .unwrap()
+                .to_string(),
+            None,
+        ),
+        (Some(None), _) => (
+            get_text!(ctx, &author.language, "MUST_BE_TEXT")
+                .unwrap()
+                .to_string(),
+            None,
+        ),
+        (Some(Some(source_code)), Some(name))
+            if !name.starts_with('#') && name.chars().count() <= SNIPPET_NAME_MAXIMUM =>
+        {
+            vars.insert("name".into(), name.to_string());
+            if Snippet::get_by_name(author, name, conn).is_ok() {
+                (
+                    strfmt(
+                        &get_text!(ctx, &author.language, "SNIPPET_EXISTS")
+                            .unwrap()
+                            .to_string(),
+                        &vars,
+                    )
+                    .unwrap(),
+                    None,
+                )
+            } else {
+                let mut tags: Vec<&str> = args[1..]
+                    .iter()
+                    .filter_map(|tag| tag.strip_prefix('#'))
+                    .filter(|tag| !tag.is_empty())
+                    .collect();
+                tags.sort_unstable();
+                tags.dedup();
+                // saved with default options, the options can be changed from the keyboard
+                let code: rpg::Code = rpg::Code::new(source_code, "stable", "debug", "2021");
+                match author.new_source_code(conn, &code).and_then(|source| {
+                    Ok((
+                        NewSnippet::new(author, &source, name, &tags).save(conn)?,
+                        source,
+                    ))
+                }) {
+                    Ok((snippet, source)) => {
+                        vars.insert("code".into(), source.code.clone());
+                        vars.insert(
+                            "tags".into(),
+                            snippet
+                                .tags()
+                                .iter()
+                                .map(|tag| format!("#{}", tag))
+                                .collect::<Vec<String>>()
+                                .join(" "),
+                        );
+                        (
+                            strfmt(
+                                &get_text!(ctx, &author.language, "SNIPPET_SAVED")
+                                    .unwrap()
+                                    .to_string(),
+                                &vars,
+                            )
+                            .unwrap()
+                                + " 📚",
+                            Some(keyboards::get_keyboard(&source, true, &author.language)),
+                        )
+                    }
+                    Err(err) => {
+                        return RpgError::from(err)
+                            .report_message(bot, message, &author.language)
+                            .await
+                    }
+                }
+            }
+        }
+        (Some(Some(_)), _) => (
+            get_text!(ctx, &author.language, "SAVE_HELP")
+                .unwrap()
+                .to_string(),
+            None,
+        ),
+    };
+    send_source_answer(bot, message.chat.id, message.id, (text, keyboard)).await;
+}
+
+/// Returns snippets message and keyboard of the given page, `None` if the page is not exist
+fn snippets_answer(
+    author: &Users,
+    page: i64,
+    conn: &mut DbConnection,
+) -> Option<(String, InlineKeyboardMarkup)> {
+    let ctx = languages_ctx();
+    let count: i64 = author.snippets_count(conn).ok()?;
+    if count == 0 {
+        return Some((
+            get_text!(ctx, &author.language, "SNIPPETS_EMPTY")
+                .unwrap()
+                .to_string()
+                + " 📚",
+            InlineKeyboardMarkup::default(),
+        ));
+    }
+    let pages: i64 = (count + SNIPPETS_PAGE_SIZE - 1) / SNIPPETS_PAGE_SIZE;
+    if !(0..pages).contains(&page) {
+        return None;
+    }
+    let snippets: Vec<(Snippet, SourceCode)> =
+        author.snippets_page(page, SNIPPETS_PAGE_SIZE, conn).ok()?;
+
+    let mut vars: HashMap<String, String> = HashMap::new();
+    vars.insert("count".into(), count.to_string());
+    vars.insert(
+        "snippets".into(),
+        snippets
+            .iter()
+            .map(|(snippet, source)| snippet_line(snippet, source))
+            .collect::<Vec<String>>()
+            .join("\n\n"),
+    );
+
+    Some((
+        limit_text(
+            &strfmt(
+                &get_text!(ctx, &author.language, "SNIPPETS_MESSAGE")
+                    .unwrap()
+                    .to_string(),
+                &vars,
+            )
+            .unwrap(),
+        ),
+        keyboards::snippets_keyboard(&snippets, author.id, page, pages),
+    ))
+}
+
+/// Snippets command handler, send the first page of the user snippets
+async fn snippets_handler(
+    bot: &AutoSend<Bot>,
+    message: &Message,
+    author: &Users,
+    conn: &mut DbConnection,
+) {
+    if let Some((text, keyboard)) = snippets_answer(author, 0, conn) {
+        bot.send_message(message.chat.id, text)
+            .reply_to_message_id(message.id)
+            .reply_markup(keyboard)
+            .send()
+            .await
+            .log_on_error()
+            .await;
+    }
+}
+
+/// Move the snippets message to another page, only the snippets owner can do it
+async fn snippets_callback(
+    bot: &AutoSend<Bot>,
+    callback_query: &CallbackQuery,
+    user_id: i32,
+    page: i64,
+    author: &Users,
+    conn: &mut DbConnection,
+) {
+    let ctx = languages_ctx();
+    if user_id != author.id {
+        bot.answer_callback_query(&callback_query.id)
+            .text(
+                get_text!(ctx, &author.language, "NOT_YOUR_SNIPPETS")
+                    .unwrap()
+                    .to_string()
+                    + " 🤨",
+            )
+            .send()
+            .await
+            .log_on_error()
+            .await;
+    } else if let Some((text, keyboard)) = snippets_answer(author, page, conn) {
+        // unwrap here because every callback query have message 🙂
+        let message: Message = callback_query.clone().message.unwrap();
+        try_join!(
+            bot.answer_callback_query(&callback_query.id).send(),
+            bot.edit_message_text(message.chat.id, message.id, text)
+                .reply_markup(keyboard)
+                .send()
+        )
+        .log_on_error()
+        .await;
+    } else {
+        cannot_reached_answer(bot, &callback_query.id, &author.language).await;
+    }
+}
+
+/// Find command handler, full-text search in the user snippets
+async fn find_handler(
+    bot: &AutoSend<Bot>,
+    message: &Message,
+    args: &[&str],
+    author: &Users,
+    conn: &mut DbConnection,
+) {
+    let ctx = languages_ctx();
+    let text: String = args.join(" ");
+    let mut vars: HashMap<String, String> = HashMap::new();
+    vars.insert("text".into(), text.clone());
+
+    let answer: (String, Option<InlineKeyboardMarkup>) = if text.is_empty() {
+        (
+            get_text!(ctx, &author.language, "FIND_HELP")
+                .unwrap()
+                .to_string(),
+            None,
+        )
+    } else {
+        let snippets: Vec<Snippet> =
+            match Snippet::search(author, &text, SEARCH_RESULTS_MAXIMUM, conn) {
+                Ok(snippets) => snippets,
+                Err(err) => {
+                    return RpgError::from(err)
+                        .report_message(bot, message, &author.language)
+                        .await
+                }
+            };
+        let results: Vec<(Snippet, SourceCode)> = snippets
+            .into_iter()
+            .filter_map(|snippet| snippet.source(conn).ok().map(|source| (snippet, source)))
+            .collect();
+
+        if results.is_empty() {
+            (
+                strfmt(
+                    &get_text!(ctx, &author.language, "FIND_EMPTY")
+                        .unwrap()
+                        .to_string(),
+                    &vars,
+                )
+                .unwrap()
+                    + " 🔎",
+                None,
+            )
+        } else {
+            vars.insert("count".into(), results.len().to_string());
+            vars.insert(
+                "snippets".into(),
+                results
+                    .iter()
+                    .map(|(snippet, source)| snippet_line(snippet, source))
+                    .collect::<Vec<String>>()
+                    .join("\n\n"),
+            );
+            (
+                limit_text(
+                    &strfmt(
+                        &get_text!(ctx, &author.language, "FIND_MESSAGE")
+                            .unwrap()
+                            .to_string(),
+                        &vars,
+                    )
+                    .unwrap(),
+                ),
+                Some(keyboards::snippets_keyboard(&results, author.id, 0, 1)),
+            )
+        }
+    };
+    send_source_answer(bot, message.chat.id, message.id, answer).await;
+}
+
+/// Delete command handler, delete user snippet by its name
+async fn delete_handler(
+    bot: &AutoSend<Bot>,
+    message: &Message,
+    name: Option<&str>,
+    author: &Users,
+    conn: &mut DbConnection,
+) {
+    let ctx = languages_ctx();
+    let text: String = if let Some(name) = name {
+        let mut vars: HashMap<String, String> = HashMap::new();
+        vars.insert("name".into(), name.to_string());
+        strfmt(
+            &get_text!(
+                ctx,
+                &author.language,
+                match Snippet::get_by_name(author, name, conn) {
+                    Ok(snippet) => {
+                        snippet.delete(conn).log_db_error();
+                        "SNIPPET_DELETED"
+                    }
+                    Err(_) => "SNIPPET_NOT_FOUND",
+                }
+            )
+            .unwrap()
+            .to_string(),
+            &vars,
+        )
+        .unwrap()
+    } else {
+        get_text!(ctx, &author.language, "DELETE_HELP")
+            .unwrap()
+            .to_string()
+    };
+
+    bot.send_message(message.chat.id, text)
+        .reply_to_message_id(message.id)
+        .send()
+        .await
+        .log_on_error()
+        .await;
+}
+
+/// Answer banned user message
+async fn banned_answer(bot: &AutoSend<Bot>, message: &Message, author: &Users) {
+    let ctx = languages_ctx();
+    bot.send_message(
+        message.chat.id,
+        get_text!(ctx, &author.language, "BANNED_MESSAGE")
+            .unwrap()
+            .to_string()
+            + &admin::ban_details(author, &author.language),
+    )
+    .reply_to_message_id(message.id)
+    .send()
+    .await
+    .log_on_error()
+    .await;
+}
+
+/// Run and Share command handler
+pub async fn command_handler(
+    bot: &AutoSend<Bot>,
+    message: &Message,
+    command: &Command,
+    author: &mut Users,
+    pool: &DbPool,
+    connection: PooledDbConnection,
+) {
+    // Share and Run command need reply message
+    if message.reply_to_message().is_some() {
+        if let Err(err) =
+            share_run_answer_message(bot, message, command, author, pool, connection).await
+        {
+            err.report_message(bot, message, &author.language).await;
+        }
+    } else {
+        let ctx = languages_ctx();
+        // If there is no reply message
+        bot.send_message(
+            message.chat.id,
+            get_text!(ctx, &author.language, "REPLY_MESSAGE")
+                .unwrap()
+                .to_string(),
+        )
+        .reply_to_message_id(message.id)
+        .send()
+        .await
+        .log_on_error()
+        .await;
+    };
+}
+
+pub async fn message_text_handler(
+    message: Message,
+    bot: AutoSend<Bot>,
+    pool: DbPool,
+    username: BotUsername,
+) {
+    // the messages without author (channel posts) are ignored
+    if let (Some(text), Some(from)) = (message.text(), message.from()) {
+        let (mut connection, mut author): (PooledDbConnection, Users) =
+            match rpg_db::connection_and_user(&pool, from).await {
+                Ok(connection_and_user) => connection_and_user,
+                Err(err) => {
+                    return err
+                        .report_message(&bot, &message, &SETTINGS.i18n.default_language)
+                        .await
+                }
+            };
+        let conn: &mut DbConnection = &mut connection;
+        if author.is_shadow_banned {
+            // shadow banned users are ignored silently
+            return;
+        }
+        if author.is_banned {
+            // banned users are answered on the commands only, nothing else is done for them
+            if text.starts_with('/') {
+                banned_answer(&bot, &message, &author).await;
+            }
+            return;
+        }
+
+        if let Some((command, args)) =
+            parse_command(&text.to_ascii_lowercase(), username.0.to_ascii_lowercase())
+        {
             let command: String = command.to_ascii_lowercase();
+            metrics::COMMANDS
+                .with_label_values(&[
+                    if USER_COMMANDS.contains(&command.as_ref())
+                        || admin::is_admin_command(&command)
+                    {
+                        &command
+                    } else {
+                        // don't make a label for every unknown command
+                        "unknown"
+                    },
+                ])
+                .inc();
             if author.can_send_command(conn)
                 || (["run", "share"].contains(&command.as_ref())
                     && message.reply_to_message().is_none())
+                // admins are not limited in admin commands
+                || (admin::is_admin_command(&command) && author.is_admin)
             {
                 let ctx = languages_ctx();
                 author
                     .update(message.from().unwrap(), conn)
                     .await
-                    .log_on_error()
-                    .await;
+                    .log_db_error();
 
                 if ["run", "share"].contains(&command.as_ref()) {
                     if message.reply_to_message().is_some() {
                         // for run and share command should have reply message to work.
                         // make record if command are work ( if there reply message )
-                        author.make_command_record(conn).log_on_error().await;
+                        author.make_command_record(conn).log_db_error();
                     };
                     let mut code_args = get_args(args).into_iter();
                     if command == "run" {
@@ -724,11 +1697,10 @@ pub async fn message_text_handler(message: Message, bot: AutoSend<Bot>) {
                                 mode: code_args.next().unwrap(),
                                 edition: code_args.next().unwrap(),
                             },
-                            &author,
-                            conn,
+                            &mut author,
+                            &pool,
+                            connection,
                         )
-                        .await
-                        .log_on_error()
                         .await;
                     } else {
                         command_handler(
@@ -739,11 +1711,10 @@ pub async fn message_text_handler(message: Message, bot: AutoSend<Bot>) {
                                 mode: code_args.next().unwrap(),
                                 edition: code_args.next().unwrap(),
                             },
-                            &author,
-                            conn,
+                            &mut author,
+                            &pool,
+                            connection,
                         )
-                        .await
-                        .log_on_error()
                         .await;
                     };
 
@@ -774,6 +1745,42 @@ pub async fn message_text_handler(message: Message, bot: AutoSend<Bot>) {
                             .unwrap()
                             .to_string(),
                     );
+                    vars.insert(
+                        "help_get".to_string(),
+                        get_text!(ctx, &author.language, "GET_HELP")
+                            .unwrap()
+                            .to_string(),
+                    );
+                    vars.insert(
+                        "help_history".to_string(),
+                        get_text!(ctx, &author.language, "HISTORY_HELP")
+                            .unwrap()
+                            .to_string(),
+                    );
+                    vars.insert(
+                        "help_save".to_string(),
+                        get_text!(ctx, &author.language, "SAVE_HELP")
+                            .unwrap()
+                            .to_string(),
+                    );
+                    vars.insert(
+                        "help_snippets".to_string(),
+                        get_text!(ctx, &author.language, "SNIPPETS_HELP")
+                            .unwrap()
+                            .to_string(),
+                    );
+                    vars.insert(
+                        "help_find".to_string(),
+                        get_text!(ctx, &author.language, "FIND_HELP")
+                            .unwrap()
+                            .to_string(),
+                    );
+                    vars.insert(
+                        "help_delete".to_string(),
+                        get_text!(ctx, &author.language, "DELETE_HELP")
+                            .unwrap()
+                            .to_string(),
+                    );
 
                     bot.send_message(
                         message.chat.id,
@@ -785,6 +1792,18 @@ pub async fn message_text_handler(message: Message, bot: AutoSend<Bot>) {
                             vars.get("help_help").unwrap().to_string()
                         } else if !args.is_empty() && args[0] == "language" {
                             vars.get("help_language").unwrap().to_string()
+                        } else if !args.is_empty() && args[0] == "get" {
+                            vars.get("help_get").unwrap().to_string()
+                        } else if !args.is_empty() && args[0] == "history" {
+                            vars.get("help_history").unwrap().to_string()
+                        } else if !args.is_empty() && args[0] == "save" {
+                            vars.get("help_save").unwrap().to_string()
+                        } else if !args.is_empty() && args[0] == "snippets" {
+                            vars.get("help_snippets").unwrap().to_string()
+                        } else if !args.is_empty() && args[0] == "find" {
+                            vars.get("help_find").unwrap().to_string()
+                        } else if !args.is_empty() && args[0] == "delete" {
+                            vars.get("help_delete").unwrap().to_string()
                         } else {
                             strfmt(
                                 &get_text!(ctx, &author.language, "HELP_MESSAGE")
@@ -802,18 +1821,18 @@ pub async fn message_text_handler(message: Message, bot: AutoSend<Bot>) {
                     .await;
                 } else if command == "start" {
                     let mut vars: HashMap<String, String> = HashMap::new();
-                    vars.insert("bot_username".to_string(), bot_username(&bot).await);
+                    vars.insert("bot_username".to_string(), username.0.clone());
                     vars.insert(
                         "attempts_maximum".to_string(),
                         (author.attempts_maximum - author.attempts).to_string(),
                     );
                     vars.insert(
                         "command_delay".to_string(),
-                        Config::get_or_add("command_delay", "15", conn).value,
+                        author.current_command_delay(conn).to_string(),
                     );
                     vars.insert(
                         "button_delay".to_string(),
-                        Config::get_or_add("button_delay", "2", conn).value,
+                        author.current_button_delay(conn).to_string(),
                     );
 
                     bot.send_message(
@@ -835,7 +1854,7 @@ pub async fn message_text_handler(message: Message, bot: AutoSend<Bot>) {
                     .log_on_error()
                     .await;
                 } else if command == "language" {
-                    author.make_command_record(conn).log_on_error().await;
+                    author.make_command_record(conn).log_db_error();
                     bot.send_message(
                         message.chat.id,
                         get_text!(ctx, &author.language, "NEW_LANGUAGE_MESSAGE")
@@ -850,20 +1869,50 @@ pub async fn message_text_handler(message: Message, bot: AutoSend<Bot>) {
                     .log_on_error()
                     .await;
                 } else if command == "info" {
-                    author.make_command_record(conn).log_on_error().await;
+                    author.make_command_record(conn).log_db_error();
                     bot.send_message(message.chat.id, info_text(&author, conn))
                         .reply_to_message_id(message.id)
                         .send()
                         .await
                         .log_on_error()
                         .await
+                } else if command == "get" {
+                    author.make_command_record(conn).log_db_error();
+                    get_handler(&bot, &message, args.first().copied(), &author, conn).await;
+                } else if command == "history" {
+                    author.make_command_record(conn).log_db_error();
+                    history_handler(&bot, &message, &author, conn).await;
+                } else if command == "save" {
+                    author.make_command_record(conn).log_db_error();
+                    save_handler(&bot, &message, &args, &author, conn).await;
+                } else if command == "snippets" {
+                    author.make_command_record(conn).log_db_error();
+                    snippets_handler(&bot, &message, &author, conn).await;
+                } else if command == "find" {
+                    author.make_command_record(conn).log_db_error();
+                    find_handler(&bot, &message, &args, &author, conn).await;
+                } else if command == "delete" {
+                    author.make_command_record(conn).log_db_error();
+                    delete_handler(&bot, &message, args.first().copied(), &author, conn).await;
+                } else if admin::is_admin_command(&command) {
+                    author.make_command_record(conn).log_db_error();
+                    admin::command_handler(&bot, &message, &command, &args, &author, &pool, conn)
+                        .await;
                 };
             } else {
                 // Cannot send command
+                let is_attempts: bool = author.attempts >= author.attempts_maximum;
+                metrics::RATE_LIMITED
+                    .with_label_values(&[if is_attempts {
+                        "attempts"
+                    } else {
+                        "command_delay"
+                    }])
+                    .inc();
                 bot.send_message(
                     message.chat.id,
-                    if author.attempts >= author.attempts_maximum {
-                        attempt_error_message(&author)
+                    if is_attempts {
+                        attempt_error_message(&author, conn)
                     } else {
                         delay_error_message(&author, true, conn)
                     },
@@ -880,108 +1929,234 @@ pub async fn message_text_handler(message: Message, bot: AutoSend<Bot>) {
     }
 }
 
-pub async fn callback_handler(bot: AutoSend<Bot>, callback_query: CallbackQuery) {
-    // callback data be like this in all callback
-    //
-    // <command> <args> <args> ..
-    // viewR <code> <already_use_keyboard>
-    // viewS <code> <already_use_keyboard>
-    // print <message_with_underscore>
-    // run <code>
-    // share <code>
-    // option <code> <option_name> <option_value>
-    // change_lang <new_language>
-
+pub async fn callback_handler(bot: AutoSend<Bot>, callback_query: CallbackQuery, pool: DbPool) {
+    // the callback data is encoded and decoded by `CallbackData`, see `callback.rs`
     if let Some(callback_data) = callback_query.data.clone() {
         log::debug!("{callback_data}");
-        let conn: &mut SqliteConnection = &mut rpg_db::establish_connection();
-        let mut author: Users = rpg_db::get_user(conn, &callback_query.from).unwrap();
+        let (mut connection, mut author): (PooledDbConnection, Users) =
+            match rpg_db::connection_and_user(&pool, &callback_query.from).await {
+                Ok(connection_and_user) => connection_and_user,
+                Err(err) => {
+                    return err
+                        .report_callback(&bot, &callback_query.id, &SETTINGS.i18n.default_language)
+                        .await
+                }
+            };
+        let conn: &mut DbConnection = &mut connection;
+
+        let callback_data: Option<CallbackData> = CallbackData::decode(&callback_data);
+        let command: &str = callback_data
+            .as_ref()
+            .map(CallbackData::command)
+            .unwrap_or("expired");
+        let chat_id: i64 = callback_query
+            .message
+            .as_ref()
+            .map(|message| message.chat.id)
+            .unwrap_or_default();
 
-        if author.can_click_button(conn) {
+        if author.is_shadow_banned {
+            // shadow banned users are ignored silently, answer without text to stop the loading
+            bot.answer_callback_query(callback_query.id)
+                .send()
+                .await
+                .log_on_error()
+                .await;
+        } else if author.is_banned {
+            let ctx = languages_ctx();
+            bot.answer_callback_query(callback_query.id)
+                .text(
+                    get_text!(ctx, &author.language, "BANNED_MESSAGE")
+                        .unwrap()
+                        .to_string()
+                        + &admin::ban_details(&author, &author.language),
+                )
+                .send()
+                .await
+                .log_on_error()
+                .await;
+        } else if let Some(CallbackData::Cancel) = callback_data {
+            // the cancel button isn't limited by the button delay and the attempts, and it doesn't
+            // make a button record (the user can cancel right after clicking run)
+            NewEvent::callback(&author, chat_id, command)
+                .save(conn)
+                .log_db_error();
+            metrics::CALLBACKS.with_label_values(&[command]).inc();
+            cancel_callback(&bot, &callback_query, &author).await;
+        } else if author.can_click_button(conn) {
             // Can click button
-            author.make_button_record(conn).log_on_error().await;
+            author.make_button_record(conn).log_db_error();
 
-            let mut args = callback_data
-                .split_whitespace()
-                .collect::<Vec<&str>>()
-                .into_iter();
-            let command: &str = args.next().expect("callback_data don't have command");
+            NewEvent::callback(&author, chat_id, command)
+                .save(conn)
+                .log_db_error();
+            metrics::CALLBACKS.with_label_values(&[command]).inc();
 
-            match command {
-                "viewR" | "viewS" => {
+            match callback_data {
+                Some(CallbackData::ViewRun {
+                    code,
+                    already_use_keyboard,
+                }) => {
                     view_handler(
                         &bot,
                         &callback_query,
-                        command,
-                        args.next().expect("viewR/viewS don't have code"),
-                        args.next()
-                            .expect("viewR/viewS don't have already_use_keyboard")
-                            .parse()
-                            .unwrap(),
-                        &author.language,
+                        "viewR",
+                        &code,
+                        already_use_keyboard,
+                        &author,
                         conn,
                     )
                     .await;
                 }
-
-                "print" => {
+                Some(CallbackData::ViewShare {
+                    code,
+                    already_use_keyboard,
+                }) => {
+                    view_handler(
+                        &bot,
+                        &callback_query,
+                        "viewS",
+                        &code,
+                        already_use_keyboard,
+                        &author,
+                        conn,
+                    )
+                    .await;
+                }
+                Some(CallbackData::Print(text_key)) => {
+                    let ctx = languages_ctx();
                     bot.answer_callback_query(&callback_query.id)
                         .text(
-                            args.next()
-                                .expect("print command don't have message to print it")
-                                .replace('_', " "),
+                            get_text!(ctx, &author.language, &text_key)
+                                .map(|text| text.to_string())
+                                .unwrap_or_else(|| "😑".into()),
                         )
                         .send()
                         .await
                         .log_on_error()
                         .await;
                 }
-
-                "run" | "share" => {
+                Some(CallbackData::Nothing) => {
+                    bot.answer_callback_query(&callback_query.id)
+                        .text("😑")
+                        .send()
+                        .await
+                        .log_on_error()
+                        .await;
+                }
+                Some(CallbackData::Run(code)) => {
                     run_share_callback(
                         &bot,
                         &callback_query,
-                        command,
-                        args.next()
-                            .expect("share/run command don't have code")
-                            .to_string(),
+                        "run",
+                        code,
                         &author.language,
-                        conn,
+                        &pool,
+                        connection,
                     )
                     .await;
                 }
-
-                "option" => {
-                    update_options(
+                Some(CallbackData::Share(code)) => {
+                    run_share_callback(
                         &bot,
                         &callback_query,
-                        args.next().expect("option command don't have code"),
-                        args.next().expect("option command don't have option_name"),
-                        args.next().expect("option command don't have option_value"),
+                        "share",
+                        code,
                         &author.language,
-                        conn,
+                        &pool,
+                        connection,
+                    )
+                    .await;
+                }
+                // handled before the button delay check
+                Some(CallbackData::Cancel) => (),
+                Some(CallbackData::Force { command, code }) => {
+                    force_callback(
+                        &bot,
+                        &callback_query,
+                        &command,
+                        &code,
+                        &author.language,
+                        &pool,
+                        connection,
                     )
                     .await;
                 }
-                "change_lang" => {
+                Some(CallbackData::Option { code, name, value }) => {
+                    update_options(&bot, &callback_query, &code, &name, &value, &author, conn)
+                        .await;
+                }
+                Some(CallbackData::ChangeLanguage(new_language)) => {
                     let message: Message = callback_query.message.unwrap();
                     change_langauge(
                         &bot,
                         &mut author,
                         message.id,
                         message.chat.id,
-                        args.next().expect("change_lang don't have new_language"),
+                        &new_language,
                         &callback_query.id,
                         conn,
                     )
                     .await;
                 }
-                _ => (),
+                Some(CallbackData::Visibility(code)) => {
+                    visibility_callback(&bot, &callback_query, &code, &author, conn).await;
+                }
+                Some(CallbackData::Config { name, value }) => {
+                    let value: Option<String> = value.as_ref().map(i64::to_string);
+                    admin::callback_handler(
+                        &bot,
+                        &callback_query,
+                        "config",
+                        &name
+                            .iter()
+                            .chain(value.iter())
+                            .map(String::as_str)
+                            .collect::<Vec<&str>>(),
+                        &author,
+                        conn,
+                    )
+                    .await;
+                }
+                Some(CallbackData::Stats(period)) => {
+                    admin::callback_handler(
+                        &bot,
+                        &callback_query,
+                        "stats",
+                        &[&period],
+                        &author,
+                        conn,
+                    )
+                    .await;
+                }
+                Some(CallbackData::Get(code)) => {
+                    get_callback(&bot, &callback_query, &code, &author, conn).await;
+                }
+                Some(CallbackData::History { user_id, page }) => {
+                    history_callback(&bot, &callback_query, user_id, page, &author, conn).await;
+                }
+                Some(CallbackData::Snippets { user_id, page }) => {
+                    snippets_callback(&bot, &callback_query, user_id, page, &author, conn).await;
+                }
+                None => {
+                    // invalid callback data, or a button from an old keyboard
+                    button_expired_error(&author.language)
+                        .report_callback(&bot, &callback_query.id, &author.language)
+                        .await;
+                }
             };
         } else {
+            let is_attempts: bool = author.attempts >= author.attempts_maximum;
+            metrics::RATE_LIMITED
+                .with_label_values(&[if is_attempts {
+                    "attempts"
+                } else {
+                    "button_delay"
+                }])
+                .inc();
             bot.answer_callback_query(callback_query.id)
-                .text(if author.attempts >= author.attempts_maximum {
-                    attempt_error_message(&author)
+                .text(if is_attempts {
+                    attempt_error_message(&author, conn)
                 } else {
                     delay_error_message(&author, false, conn)
                 })
@@ -999,25 +2174,33 @@ async fn view_handler(
     view: &str,
     code: &str,
     already_use_keyboard: bool,
-    language: &str,
-    conn: &mut SqliteConnection,
+    author: &Users,
+    conn: &mut DbConnection,
 ) {
+    let language: &str = &author.language;
     if already_use_keyboard {
         already_use_answer(bot, &callback_query.id, language, view == "viewR").await;
     } else if let Ok(source) = SourceCode::get_by_code(code, conn) {
         // unwrap here because every callback query have message 🙂
         let message: Message = callback_query.clone().message.unwrap();
+        // only the author can change the options, the others can run/share it as it is
+        let is_author: bool = source.is_author(author);
         let keyboard: InlineKeyboardMarkup = if view == "viewR" {
-            keyboards::run_keyboard(source, language)
+            keyboards::run_keyboard(source, is_author, language)
         } else {
-            keyboards::share_keyboard(source, language)
+            keyboards::share_keyboard(source, is_author, language)
         };
 
-        bot.edit_message_reply_markup(message.chat.id, message.id)
+        if let Err(err) = bot
+            .edit_message_reply_markup(message.chat.id, message.id)
             .reply_markup(keyboard)
             .send()
             .await
-            .unwrap();
+        {
+            RpgError::from(err)
+                .report_callback(bot, &callback_query.id, language)
+                .await;
+        }
     } else {
         cannot_reached_answer(bot, &callback_query.id, language).await;
     }
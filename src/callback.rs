@@ -0,0 +1,325 @@
+// rpg_bot - Telegram bot 🤖, help you to run and share Rust code in Telegram via Rust playground 🦀
+// Source code: <https://github.com/TheAwiteb/rpg_bot>
+//
+// Copyright (C) 2022 TheAwiteb <awiteb@hotmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use teloxide::types::InlineKeyboardButton;
+
+/// Version of the callback data format, increase it when the format of any
+/// callback changes, so the buttons of the old keyboards will be expired instead of misread
+const VERSION: &str = "1";
+
+/// Maximum length of the callback data in bytes (Telegram limit)
+pub const CALLBACK_DATA_MAXIMUM: usize = 64;
+
+/// Callback data of the keyboards buttons
+///
+/// It is encoded like this `<version> <command> <args> <args> ..`
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallbackData {
+    /// `viewR <code> <already_use_keyboard>` Show the run keyboard of the source code
+    ViewRun {
+        code: String,
+        already_use_keyboard: bool,
+    },
+    /// `viewS <code> <already_use_keyboard>` Show the share keyboard of the source code
+    ViewShare {
+        code: String,
+        already_use_keyboard: bool,
+    },
+    /// `print <text_key>` Answer with the text of the key in the user language
+    Print(String),
+    /// `nothing` Answer with 😑, for the empty buttons
+    Nothing,
+    /// `run <code>`
+    Run(String),
+    /// `share <code>`
+    Share(String),
+    /// `option <code> <option_name> <option_value>`
+    Option {
+        code: String,
+        name: String,
+        value: String,
+    },
+    /// `change_lang <new_language>`
+    ChangeLanguage(String),
+    /// `visibility <code>`
+    Visibility(String),
+    /// `get <code>`
+    Get(String),
+    /// `history <user_id> <page>`
+    History { user_id: i32, page: i64 },
+    /// `snippets <user_id> <page>`
+    Snippets { user_id: i32, page: i64 },
+    /// `force <command> <code>` Run/Share the source code without the cached result
+    Force { command: String, code: String },
+    /// `config <name (optional)> <value (optional)>`
+    Config {
+        name: Option<String>,
+        value: Option<i64>,
+    },
+    /// `stats <period>`
+    Stats(String),
+    /// `cancel` Cancel the run/share of the wait message
+    Cancel,
+}
+
+impl CallbackData {
+    /// Returns the command of the callback
+    pub fn command(&self) -> &'static str {
+        match self {
+            Self::ViewRun { .. } => "viewR",
+            Self::ViewShare { .. } => "viewS",
+            Self::Print(_) => "print",
+            Self::Nothing => "nothing",
+            Self::Run(_) => "run",
+            Self::Share(_) => "share",
+            Self::Option { .. } => "option",
+            Self::ChangeLanguage(_) => "change_lang",
+            Self::Visibility(_) => "visibility",
+            Self::Get(_) => "get",
+            Self::History { .. } => "history",
+            Self::Snippets { .. } => "snippets",
+            Self::Force { .. } => "force",
+            Self::Config { .. } => "config",
+            Self::Stats(_) => "stats",
+            Self::Cancel => "cancel",
+        }
+    }
+
+    /// Returns the arguments of the callback, the spaces are replaced with underscore
+    fn args(&self) -> Vec<String> {
+        match self {
+            Self::ViewRun {
+                code,
+                already_use_keyboard,
+            }
+            | Self::ViewShare {
+                code,
+                already_use_keyboard,
+            } => vec![code.clone(), already_use_keyboard.to_string()],
+            Self::Print(arg)
+            | Self::Run(arg)
+            | Self::Share(arg)
+            | Self::ChangeLanguage(arg)
+            | Self::Visibility(arg)
+            | Self::Get(arg)
+            | Self::Stats(arg) => vec![arg.replace(' ', "_")],
+            Self::Nothing | Self::Cancel => Vec::new(),
+            Self::Option { code, name, value } => vec![code.clone(), name.clone(), value.clone()],
+            Self::History { user_id, page } | Self::Snippets { user_id, page } => {
+                vec![user_id.to_string(), page.to_string()]
+            }
+            Self::Force { command, code } => vec![command.clone(), code.clone()],
+            Self::Config { name, value } => name
+                .iter()
+                .cloned()
+                .chain(value.iter().map(i64::to_string))
+                .collect(),
+        }
+    }
+
+    /// Encode the callback data, if the encoded data is longer than Telegram limit,
+    /// it will be logged and [`CallbackData::Nothing`] will be encoded instead
+    pub fn encode(&self) -> String {
+        let data: String = [VERSION, self.command()]
+            .into_iter()
+            .map(str::to_string)
+            .chain(self.args())
+            .collect::<Vec<String>>()
+            .join(" ");
+        if data.len() > CALLBACK_DATA_MAXIMUM {
+            log::error!("The callback data is longer than {CALLBACK_DATA_MAXIMUM} bytes: {data}");
+            Self::Nothing.encode()
+        } else {
+            data
+        }
+    }
+
+    /// Decode the callback data, returns [`None`] if the data is invalid or from an old version
+    pub fn decode(data: &str) -> Option<Self> {
+        if data.len() > CALLBACK_DATA_MAXIMUM {
+            return None;
+        }
+        let mut parts = data.split_whitespace();
+        if parts.next()? != VERSION {
+            return None;
+        }
+        let command: &str = parts.next()?;
+        let args: Vec<&str> = parts.collect();
+
+        let callback_data = match (command, args.as_slice()) {
+            ("viewR", [code, already_use_keyboard]) => Self::ViewRun {
+                code: code.to_string(),
+                already_use_keyboard: already_use_keyboard.parse().ok()?,
+            },
+            ("viewS", [code, already_use_keyboard]) => Self::ViewShare {
+                code: code.to_string(),
+                already_use_keyboard: already_use_keyboard.parse().ok()?,
+            },
+            ("print", [text_key]) => Self::Print(text_key.to_string()),
+            ("nothing", []) => Self::Nothing,
+            ("run", [code]) => Self::Run(code.to_string()),
+            ("share", [code]) => Self::Share(code.to_string()),
+            ("option", [code, name, value]) => Self::Option {
+                code: code.to_string(),
+                name: name.to_string(),
+                value: value.to_string(),
+            },
+            ("change_lang", [new_language]) => Self::ChangeLanguage(new_language.replace('_', " ")),
+            ("visibility", [code]) => Self::Visibility(code.to_string()),
+            ("get", [code]) => Self::Get(code.to_string()),
+            ("history", [user_id, page]) => Self::History {
+                user_id: user_id.parse().ok()?,
+                page: page.parse().ok()?,
+            },
+            ("snippets", [user_id, page]) => Self::Snippets {
+                user_id: user_id.parse().ok()?,
+                page: page.parse().ok()?,
+            },
+            ("force", [command, code]) => Self::Force {
+                command: command.to_string(),
+                code: code.to_string(),
+            },
+            ("config", []) => Self::Config {
+                name: None,
+                value: None,
+            },
+            ("config", [name]) => Self::Config {
+                name: Some(name.to_string()),
+                value: None,
+            },
+            ("config", [name, value]) => Self::Config {
+                name: Some(name.to_string()),
+                value: Some(value.parse().ok()?),
+            },
+            ("stats", [period]) => Self::Stats(period.to_string()),
+            ("cancel", []) => Self::Cancel,
+            _ => return None,
+        };
+        Some(callback_data)
+    }
+
+    /// Returns callback button with the encoded callback data
+    pub fn button(&self, text: impl Into<String>) -> InlineKeyboardButton {
+        InlineKeyboardButton::callback(text.into(), self.encode())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_callbacks() -> Vec<CallbackData> {
+        vec![
+            CallbackData::ViewRun {
+                code: "abcd".into(),
+                already_use_keyboard: true,
+            },
+            CallbackData::ViewShare {
+                code: "abcd".into(),
+                already_use_keyboard: false,
+            },
+            CallbackData::Print("NOT_FOUND".into()),
+            CallbackData::Nothing,
+            CallbackData::Run("abcd".into()),
+            CallbackData::Share("abcd".into()),
+            CallbackData::Option {
+                code: "abcd".into(),
+                name: "version".into(),
+                value: "nightly".into(),
+            },
+            CallbackData::ChangeLanguage("English 🇺🇸".into()),
+            CallbackData::Visibility("abcd".into()),
+            CallbackData::Get("abcd".into()),
+            CallbackData::History {
+                user_id: 42,
+                page: 3,
+            },
+            CallbackData::Snippets {
+                user_id: 42,
+                page: 0,
+            },
+            CallbackData::Force {
+                command: "run".into(),
+                code: "abcd".into(),
+            },
+            CallbackData::Config {
+                name: None,
+                value: None,
+            },
+            CallbackData::Config {
+                name: Some("command_delay".into()),
+                value: None,
+            },
+            CallbackData::Config {
+                name: Some("command_delay".into()),
+                value: Some(-5),
+            },
+            CallbackData::Stats("week".into()),
+            CallbackData::Cancel,
+        ]
+    }
+
+    #[test]
+    fn round_trip() {
+        for callback in all_callbacks() {
+            let data: String = callback.encode();
+            assert!(data.len() <= CALLBACK_DATA_MAXIMUM, "{data}");
+            assert_eq!(CallbackData::decode(&data), Some(callback), "{data}");
+        }
+    }
+
+    #[test]
+    fn encode_too_long() {
+        let callback = CallbackData::Print("A".repeat(CALLBACK_DATA_MAXIMUM));
+        assert_eq!(callback.encode(), CallbackData::Nothing.encode());
+    }
+
+    #[test]
+    fn decode_too_long() {
+        let data: String = format!("{VERSION} print {}", "A".repeat(CALLBACK_DATA_MAXIMUM));
+        assert_eq!(CallbackData::decode(&data), None);
+    }
+
+    #[test]
+    fn decode_other_version() {
+        for callback in all_callbacks() {
+            let data: String = callback.encode();
+            let (_, rest) = data.split_once(' ').unwrap();
+            assert_eq!(CallbackData::decode(&format!("0 {rest}")), None);
+            // the old format, without version
+            assert_eq!(CallbackData::decode(rest), None);
+        }
+    }
+
+    #[test]
+    fn decode_invalid() {
+        for data in [
+            "",
+            VERSION,
+            "1 unknown",
+            "1 run",
+            "1 run abcd extra",
+            "1 viewR abcd maybe",
+            "1 history 42 page",
+            "1 config command_delay five",
+            "1 nothing extra",
+        ] {
+            assert_eq!(CallbackData::decode(data), None, "{data}");
+        }
+    }
+}
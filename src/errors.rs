@@ -0,0 +1,133 @@
+// rpg_bot - Telegram bot 🤖, help you to run and share Rust code in Telegram via Rust playground 🦀
+// Source code: <https://github.com/TheAwiteb/rpg_bot>
+//
+// Copyright (C) 2022 TheAwiteb <awiteb@hotmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{metrics, models::DieselError, rpg_db::languages_ctx};
+use diesel::r2d2::PoolError;
+use json_gettext::get_text;
+use std::fmt;
+use teloxide::{prelude2::*, types::Message, RequestError};
+
+/// The bot errors, every error have a message for the user, see [`RpgError::user_message`]
+#[derive(Debug)]
+pub enum RpgError {
+    /// Cannot get a database connection from the pool
+    Connection(PoolError),
+    /// Database query error
+    Diesel(DieselError),
+    /// Telegram Bot API error
+    Telegram(RequestError),
+    /// Rust playground error, unreachable or invalid response
+    Playground(String),
+    /// The Rust playground is down (the circuit breaker is open), the request is not sent
+    PlaygroundUnavailable,
+    /// Invalid input, the message is sent to the user as it is
+    Validation(String),
+}
+
+pub type RpgResult<T> = Result<T, RpgError>;
+
+impl fmt::Display for RpgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Connection(err) => write!(f, "Database connection error: {}", err),
+            Self::Diesel(err) => write!(f, "Database error: {}", err),
+            Self::Telegram(err) => write!(f, "Telegram error: {}", err),
+            Self::Playground(err) => write!(f, "Rust playground error: {}", err),
+            Self::PlaygroundUnavailable => write!(f, "Rust playground is unavailable"),
+            Self::Validation(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for RpgError {}
+
+impl From<PoolError> for RpgError {
+    fn from(err: PoolError) -> Self {
+        Self::Connection(err)
+    }
+}
+
+impl From<DieselError> for RpgError {
+    fn from(err: DieselError) -> Self {
+        Self::Diesel(err)
+    }
+}
+
+impl From<RequestError> for RpgError {
+    fn from(err: RequestError) -> Self {
+        Self::Telegram(err)
+    }
+}
+
+impl From<reqwest::Error> for RpgError {
+    fn from(err: reqwest::Error) -> Self {
+        Self::Playground(err.to_string())
+    }
+}
+
+impl RpgError {
+    /// Returns the error message for the user in the user language,
+    /// the internal details are not included (they are logged)
+    pub fn user_message(&self, language: &str) -> String {
+        let ctx = languages_ctx();
+        let text_key: &str = match self {
+            Self::Validation(message) => return message.clone(),
+            Self::Connection(_) | Self::Diesel(_) => "DATABASE_ERROR",
+            Self::Telegram(_) => "TELEGRAM_ERROR",
+            Self::Playground(_) => "PLAYGROUND_ERROR",
+            Self::PlaygroundUnavailable => "PLAYGROUND_UNAVAILABLE",
+        };
+        get_text!(ctx, language, text_key).unwrap().to_string() + " 😵"
+    }
+
+    /// Log the error, the database errors are counted in the metrics
+    pub fn log(&self) {
+        match self {
+            Self::Validation(_) => (),
+            Self::Connection(_) | Self::Diesel(_) => {
+                metrics::DB_ERRORS.inc();
+                log::error!("{}", self);
+            }
+            // the failures that opened the circuit breaker are logged
+            Self::PlaygroundUnavailable => log::debug!("{}", self),
+            _ => log::error!("{}", self),
+        }
+    }
+
+    /// Log the error and reply to the message with the error message
+    pub async fn report_message(&self, bot: &AutoSend<Bot>, message: &Message, language: &str) {
+        self.log();
+        bot.send_message(message.chat.id, self.user_message(language))
+            .reply_to_message_id(message.id)
+            .send()
+            .await
+            .log_on_error()
+            .await;
+    }
+
+    /// Log the error and answer the callback query with the error message
+    pub async fn report_callback(&self, bot: &AutoSend<Bot>, query_id: &str, language: &str) {
+        self.log();
+        bot.answer_callback_query(query_id)
+            .text(self.user_message(language))
+            .send()
+            .await
+            .log_on_error()
+            .await;
+    }
+}
@@ -0,0 +1,112 @@
+// rpg_bot - Telegram bot 🤖, help you to run and share Rust code in Telegram via Rust playground 🦀
+// Source code: <https://github.com/TheAwiteb/rpg_bot>
+//
+// Copyright (C) 2022 TheAwiteb <awiteb@hotmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{
+    migrations, rpg,
+    rpg_db::{DbPool, PooledDbConnection},
+    settings::SETTINGS,
+};
+use diesel::connection::SimpleConnection;
+use hyper::{Body, Response, StatusCode};
+
+/// Result of one readiness check
+enum Check {
+    Ok,
+    /// The check is disabled or cannot be done
+    Skipped(String),
+    Failed(String),
+}
+
+impl Check {
+    fn is_failed(&self) -> bool {
+        matches!(self, Check::Failed(_))
+    }
+
+    fn text(&self) -> String {
+        match self {
+            Check::Ok => "ok".into(),
+            Check::Skipped(reason) => format!("skipped ({})", reason),
+            Check::Failed(reason) => format!("failed ({})", reason),
+        }
+    }
+}
+
+/// Returns `GET /health` response, the bot is alive if it can answer
+pub fn liveness() -> Response<Body> {
+    Response::new(Body::from("ok"))
+}
+
+/// Check the database connection (from the pool) and the migrations, it's blocking
+fn database_checks(pool: &DbPool) -> (Check, Check) {
+    let mut connection: PooledDbConnection =
+        match pool.get().map_err(|err| err.to_string()).and_then(|conn| {
+            conn.batch_execute("SELECT 1")
+                .map(|_| conn)
+                .map_err(|err| err.to_string())
+        }) {
+            Ok(conn) => conn,
+            Err(err) => return (Check::Failed(err), Check::Failed("no database".into())),
+        };
+
+    let migrations: Check = match migrations::pending(&mut connection) {
+        Ok(pending) if pending.is_empty() => Check::Ok,
+        Ok(pending) => Check::Failed(format!("pending migrations: {}", pending.join(", "))),
+        Err(err) => Check::Failed(err.to_string()),
+    };
+    (Check::Ok, migrations)
+}
+
+/// Returns `GET /ready` response, with every check result. The status is `503` if any check failed
+///
+/// The Rust playground check can be disabled with `ready_check_playground` setting (`READY_CHECK_PLAYGROUND=false`)
+pub async fn readiness(pool: DbPool) -> Response<Body> {
+    let (database, migrations) = tokio::task::spawn_blocking(move || database_checks(&pool))
+        .await
+        .unwrap_or_else(|err| {
+            let check = || Check::Failed(err.to_string());
+            (check(), check())
+        });
+    let playground: Check = if !SETTINGS.server.ready_check_playground {
+        Check::Skipped("disabled".into())
+    } else {
+        match rpg::is_reachable().await {
+            Ok(()) => Check::Ok,
+            Err(err) => Check::Failed(err.to_string()),
+        }
+    };
+
+    let checks: [(&str, Check); 3] = [
+        ("database", database),
+        ("migrations", migrations),
+        ("playground", playground),
+    ];
+    Response::builder()
+        .status(if checks.iter().any(|(_, check)| check.is_failed()) {
+            StatusCode::SERVICE_UNAVAILABLE
+        } else {
+            StatusCode::OK
+        })
+        .body(Body::from(
+            checks
+                .iter()
+                .map(|(name, check)| format!("{}: {}", name, check.text()))
+                .collect::<Vec<String>>()
+                .join("\n"),
+        ))
+        .unwrap()
+}
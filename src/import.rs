@@ -0,0 +1,83 @@
+// rpg_bot - Telegram bot 🤖, help you to run and share Rust code in Telegram via Rust playground 🦀
+// Source code: <https://github.com/TheAwiteb/rpg_bot>
+//
+// Copyright (C) 2022 TheAwiteb <awiteb@hotmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Copy the data of SQLite database into PostgreSQL database, see `--import-sqlite` in the README
+
+use crate::{
+    models::{CachedResult, Config, Event, ModerationLog, Snippet, SourceCode, Users},
+    rpg_db::DbConnection,
+    schema::{cached_results, config, events, moderation_logs, snippets, source_codes, users},
+};
+use diesel::{connection::SimpleConnection, pg::PgConnection, prelude::*};
+use std::error::Error;
+
+/// Maximum rows in one insert query, PostgreSQL accepts 65535 parameters in one query
+const CHUNK_SIZE: usize = 1000;
+
+/// Copy all rows of the table from SQLite connection to PostgreSQL connection
+macro_rules! copy_table {
+    ($table:ident, $model:ty, $sqlite:expr, $postgres:expr) => {{
+        let rows: Vec<$model> = $table::table.load::<$model>($sqlite)?;
+        for chunk in rows.chunks(CHUNK_SIZE) {
+            diesel::insert_into($table::table)
+                .values(chunk)
+                .execute($postgres)?;
+        }
+        println!("{}: {} rows", stringify!($table), rows.len());
+    }};
+}
+
+/// Copy the data of the SQLite database into the PostgreSQL database in one transaction,
+/// the PostgreSQL database should be empty (it is migrated before the import)
+pub fn import_sqlite(sqlite_path: &str, postgres_url: &str) -> Result<(), Box<dyn Error>> {
+    if !DbConnection::is_postgres_url(postgres_url) {
+        return Err("DATABASE_URL must be PostgreSQL url".into());
+    }
+    let sqlite = SqliteConnection::establish(sqlite_path)?;
+    let postgres = PgConnection::establish(postgres_url)?;
+    if users::table.count().get_result::<i64>(&postgres)? != 0 {
+        return Err("The PostgreSQL database is not empty".into());
+    }
+
+    postgres.transaction::<_, Box<dyn Error>, _>(|| {
+        // the parent tables first, because of the foreign keys
+        copy_table!(users, Users, &sqlite, &postgres);
+        copy_table!(source_codes, SourceCode, &sqlite, &postgres);
+        copy_table!(snippets, Snippet, &sqlite, &postgres);
+        copy_table!(cached_results, CachedResult, &sqlite, &postgres);
+        copy_table!(config, Config, &sqlite, &postgres);
+        copy_table!(events, Event, &sqlite, &postgres);
+        copy_table!(moderation_logs, ModerationLog, &sqlite, &postgres);
+
+        // the ids are copied, so the sequences should continue after them
+        for table in [
+            "users",
+            "source_codes",
+            "snippets",
+            "cached_results",
+            "config",
+            "events",
+            "moderation_logs",
+        ] {
+            postgres.batch_execute(&format!(
+                "SELECT setval(pg_get_serial_sequence('{table}', 'id'), COALESCE(MAX(id), 0) + 1, false) FROM {table}"
+            ))?;
+        }
+        Ok(())
+    })
+}
@@ -16,7 +16,12 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
-use crate::{models::SourceCode, rpg_db::languages_ctx};
+use crate::{
+    callback::CallbackData,
+    models::{KnownConfig, Snippet, SourceCode},
+    rpg_db::languages_ctx,
+    stats,
+};
 use json_gettext::get_text;
 use reqwest::Url;
 use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
@@ -48,27 +53,12 @@ fn option_keyboard(
     let ctx = languages_ctx();
 
     let mut keyboard: InlineKeyboardMarkup = InlineKeyboardMarkup::new([[
-        InlineKeyboardButton::callback(
-            get_text!(ctx, language, "VERSION").unwrap().to_string() + " 📦\u{200B}",
-            format!(
-                "print {}",
-                get_text!(ctx, language, "VERSION_OF_CODE").unwrap()
-            ) + "_📦\u{200B}",
-        ),
-        InlineKeyboardButton::callback(
-            get_text!(ctx, language, "MODE").unwrap().to_string() + " 🚀",
-            format!(
-                "print {}",
-                get_text!(ctx, language, "MODE_OF_CODE").unwrap()
-            ) + "_🚀\u{200B}",
-        ),
-        InlineKeyboardButton::callback(
-            get_text!(ctx, language, "EDITION").unwrap().to_string() + " ⚡\u{200B}",
-            format!(
-                "print {}",
-                get_text!(ctx, language, "EDITION_OF_CODE").unwrap()
-            ) + "_⚡",
-        ),
+        CallbackData::Print("VERSION_OF_CODE".into())
+            .button(get_text!(ctx, language, "VERSION").unwrap().to_string() + " 📦\u{200B}"),
+        CallbackData::Print("MODE_OF_CODE".into())
+            .button(get_text!(ctx, language, "MODE").unwrap().to_string() + " 🚀"),
+        CallbackData::Print("EDITION_OF_CODE".into())
+            .button(get_text!(ctx, language, "EDITION").unwrap().to_string() + " ⚡\u{200B}"),
     ]]);
     let buttons: [&str; 9] = [
         "Stable", "Debug", "2015", "Beta", "Release", "2018", "Nightly", "-", "2021",
@@ -77,23 +67,25 @@ fn option_keyboard(
         keyboard = keyboard.append_row(row.iter().enumerate().map(|(idx, button)| {
             let args: Vec<&str> = vec![version, mode, edition];
             let it_same: bool = button.to_lowercase() == args[idx];
-            InlineKeyboardButton::callback(
-                format!("{} {}", button, if it_same { check } else { uncheck }),
-                if button == &"-" {
-                    "print 😑".into()
-                } else {
-                    format!(
-                        "option {} {} {}",
-                        code,
-                        match idx {
-                            0 => "version",
-                            1 => "mode",
-                            _ => "edition",
-                        },
-                        button.to_lowercase()
-                    )
-                },
-            )
+            if button == &"-" {
+                CallbackData::Nothing
+            } else {
+                CallbackData::Option {
+                    code: code.to_string(),
+                    name: match idx {
+                        0 => "version",
+                        1 => "mode",
+                        _ => "edition",
+                    }
+                    .to_string(),
+                    value: button.to_lowercase(),
+                }
+            }
+            .button(format!(
+                "{} {}",
+                button,
+                if it_same { check } else { uncheck }
+            ))
         }));
     }
     keyboard
@@ -117,18 +109,16 @@ pub fn view_run_keyboard(
     language: &str,
 ) -> InlineKeyboardMarkup {
     let ctx = languages_ctx();
-    InlineKeyboardMarkup::new([[InlineKeyboardButton::callback(
-        get_text!(ctx, language, "RUN").unwrap().to_string() + " 🦀⚙️",
-        if is_valid_source {
-            // if source code is valid, the code will be valid
-            format!("viewR {} {}", code.as_ref(), already_use_keyboard)
-        } else {
-            format!(
-                "print {}",
-                get_text!(ctx, language, "CANNOT_RUN_INVALID_CODE").unwrap()
-            )
-        },
-    )]])
+    InlineKeyboardMarkup::new([[if is_valid_source {
+        // if source code is valid, the code will be valid
+        CallbackData::ViewRun {
+            code: code.as_ref().to_string(),
+            already_use_keyboard,
+        }
+    } else {
+        CallbackData::Print("CANNOT_RUN_INVALID_CODE".into())
+    }
+    .button(get_text!(ctx, language, "RUN").unwrap().to_string() + " 🦀⚙️")]])
 }
 
 pub fn view_share_keyboard(
@@ -139,50 +129,282 @@ pub fn view_share_keyboard(
 ) -> InlineKeyboardMarkup {
     let ctx = languages_ctx();
 
-    InlineKeyboardMarkup::new([[InlineKeyboardButton::callback(
-        get_text!(ctx, language, "SHARE").unwrap().to_string() + " 🦀🔗",
-        if is_valid_source {
-            // if source code is valid, the code will be valid
-            format!("viewS {} {}", code.as_ref(), already_use_keyboard)
+    InlineKeyboardMarkup::new([[if is_valid_source {
+        // if source code is valid, the code will be valid
+        CallbackData::ViewShare {
+            code: code.as_ref().to_string(),
+            already_use_keyboard,
+        }
+    } else {
+        CallbackData::Print("CANNOT_SHARE_INVALID_CODE".into())
+    }
+    .button(get_text!(ctx, language, "SHARE").unwrap().to_string() + " 🦀🔗")]])
+}
+
+pub fn get_keyboard(source: &SourceCode, is_author: bool, language: &str) -> InlineKeyboardMarkup {
+    let ctx = languages_ctx();
+
+    let keyboard = if is_author {
+        InlineKeyboardMarkup::new([[
+            view_run_keyboard(&source.code, false, true, language).inline_keyboard[0][0].clone(),
+            view_share_keyboard(&source.code, false, true, language).inline_keyboard[0][0].clone(),
+        ]])
+    } else {
+        // the options buttons change the saved source code, the others can only run/share it
+        InlineKeyboardMarkup::new([[
+            CallbackData::Run(source.code.clone())
+                .button(get_text!(ctx, language, "RUN").unwrap().to_string() + " 🦀⚙️"),
+            CallbackData::Share(source.code.clone())
+                .button(get_text!(ctx, language, "SHARE").unwrap().to_string() + " 🦀🔗"),
+        ]])
+    };
+
+    if is_author {
+        // only the author can change the source code visibility
+        keyboard.append_row([CallbackData::Visibility(source.code.clone()).button(
+            if source.is_public {
+                get_text!(ctx, language, "MAKE_PRIVATE")
+                    .unwrap()
+                    .to_string()
+                    + " 🔒"
+            } else {
+                get_text!(ctx, language, "MAKE_PUBLIC").unwrap().to_string() + " 🌐"
+            },
+        )])
+    } else {
+        keyboard
+    }
+}
+
+/// Returns the pages row, `page_callback` returns the callback data of the page
+/// and `page_text` is the text key of the page number
+fn pages_row(
+    page_callback: impl Fn(i64) -> CallbackData,
+    page: i64,
+    pages: i64,
+    page_text: &str,
+) -> Vec<InlineKeyboardButton> {
+    vec![
+        if page > 0 {
+            page_callback(page - 1).button("⬅️")
         } else {
-            format!(
-                "print {}",
-                get_text!(ctx, language, "CANNOT_SHARE_INVALID_CODE").unwrap()
-            )
+            CallbackData::Nothing.button("-")
         },
-    )]])
+        CallbackData::Print(page_text.into()).button(format!("{}/{}", page + 1, pages)),
+        if page + 1 < pages {
+            page_callback(page + 1).button("➡️")
+        } else {
+            CallbackData::Nothing.button("-")
+        },
+    ]
 }
 
-pub fn run_keyboard(source: SourceCode, language: &str) -> InlineKeyboardMarkup {
+pub fn history_keyboard(
+    sources: &[SourceCode],
+    user_id: i32,
+    page: i64,
+    pages: i64,
+) -> InlineKeyboardMarkup {
+    // keyboard will be like this
+    //
+    //  abcd | fn main() {
+    //  efgh | use std::io;
+    //   ⬅️  |   2/3   |  ➡️
+    //
+    let keyboard = InlineKeyboardMarkup::new(sources.iter().map(|source| {
+        [CallbackData::Get(source.code.clone()).button(format!(
+            "{} | {}",
+            source.code,
+            source_preview(&source.source_code)
+        ))]
+    }));
+
+    if pages > 1 {
+        keyboard.append_row(pages_row(
+            |page| CallbackData::History { user_id, page },
+            page,
+            pages,
+            "PAGE_OF_HISTORY",
+        ))
+    } else {
+        keyboard
+    }
+}
+
+/// Returns snippets keyboard, the pages row will not be added if there is one page
+pub fn snippets_keyboard(
+    snippets: &[(Snippet, SourceCode)],
+    user_id: i32,
+    page: i64,
+    pages: i64,
+) -> InlineKeyboardMarkup {
+    // keyboard will be like this
+    //
+    //  hello | fn main() {
+    //  input | use std::io;
+    //   ⬅️  |   2/3   |  ➡️
+    //
+    let keyboard = InlineKeyboardMarkup::new(snippets.iter().map(|(snippet, source)| {
+        [CallbackData::Get(source.code.clone()).button(format!(
+            "{} | {}",
+            snippet.name,
+            source_preview(&source.source_code)
+        ))]
+    }));
+
+    if pages > 1 {
+        keyboard.append_row(pages_row(
+            |page| CallbackData::Snippets { user_id, page },
+            page,
+            pages,
+            "PAGE_OF_SNIPPETS",
+        ))
+    } else {
+        keyboard
+    }
+}
+
+/// Returns the first line of the source code, to use it as a preview
+pub fn source_preview(source_code: &str) -> String {
+    let line: &str = source_code
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+    if line.chars().count() > 30 {
+        line.chars().take(29).collect::<String>() + "…"
+    } else {
+        line.to_string()
+    }
+}
+
+/// Add force button to the keyboard, to run/share the source code again without the cached result
+pub fn force_keyboard(
+    keyboard: InlineKeyboardMarkup,
+    command: &str,
+    code: &str,
+    language: &str,
+) -> InlineKeyboardMarkup {
     let ctx = languages_ctx();
+    keyboard.append_row([CallbackData::Force {
+        command: command.to_string(),
+        code: code.to_string(),
+    }
+    .button(get_text!(ctx, language, "FORCE_RERUN").unwrap().to_string() + " 🔄")])
+}
 
-    option_keyboard(
-        &source.version,
-        &source.mode,
-        &source.edition,
-        &source.code,
-        language,
-    )
-    .append_row([InlineKeyboardButton::callback(
-        get_text!(ctx, language, "RUN").unwrap().to_string() + " 🦀⚙️",
-        format!("run {}", source.code),
-    )])
+/// Returns the keyboard of the wait message, with cancel button
+pub fn cancel_keyboard(language: &str) -> InlineKeyboardMarkup {
+    let ctx = languages_ctx();
+    InlineKeyboardMarkup::new([[CallbackData::Cancel
+        .button(get_text!(ctx, language, "CANCEL").unwrap().to_string() + " ✖️")]])
+}
+
+/// Returns configs keyboard, click on config to edit it
+pub fn configs_keyboard(configs: &[(&KnownConfig, i64)]) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(configs.iter().map(|(known, value)| {
+        [CallbackData::Config {
+            name: Some(known.name.to_string()),
+            value: None,
+        }
+        .button(format!("{}: {}", known.name, value))]
+    }))
+}
+
+/// Returns config edit keyboard, the value will not be out of the allowed range
+pub fn config_keyboard(known: &KnownConfig, value: i64, language: &str) -> InlineKeyboardMarkup {
+    // keyboard will be like this
+    //
+    //  ➖ 5 | ➕ 5
+    //  Reset to default
+    //  ⬅️ Back
+    //
+    let ctx = languages_ctx();
+    let step_button = |new_value: i64, text: String| {
+        if (known.minimum..=known.maximum).contains(&new_value) {
+            CallbackData::Config {
+                name: Some(known.name.to_string()),
+                value: Some(new_value),
+            }
+            .button(text)
+        } else {
+            CallbackData::Nothing.button("-")
+        }
+    };
+
+    InlineKeyboardMarkup::new([
+        vec![
+            step_button(value - known.step, format!("➖ {}", known.step)),
+            step_button(value + known.step, format!("➕ {}", known.step)),
+        ],
+        vec![CallbackData::Config {
+            name: Some(known.name.to_string()),
+            value: Some(known.default_value()),
+        }
+        .button(
+            get_text!(ctx, language, "RESET_DEFAULT")
+                .unwrap()
+                .to_string()
+                + " 🔄",
+        )],
+        vec![CallbackData::Config {
+            name: None,
+            value: None,
+        }
+        .button("⬅️ ".to_string() + &get_text!(ctx, language, "BACK").unwrap().to_string())],
+    ])
+}
+
+/// Returns statistics periods keyboard, the current period is marked
+pub fn stats_keyboard(period: &str) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new([stats::PERIODS
+        .iter()
+        .map(|(name, _)| {
+            CallbackData::Stats(name.to_string()).button(format!(
+                "{}{}",
+                if name == &period { "🌟 " } else { "" },
+                name
+            ))
+        })
+        .collect::<Vec<InlineKeyboardButton>>()])
 }
 
-pub fn share_keyboard(source: SourceCode, language: &str) -> InlineKeyboardMarkup {
+/// Returns the options keyboard with the run button, only the run button if the user is not the author
+pub fn run_keyboard(source: SourceCode, is_author: bool, language: &str) -> InlineKeyboardMarkup {
     let ctx = languages_ctx();
 
-    option_keyboard(
-        &source.version,
-        &source.mode,
-        &source.edition,
-        &source.code,
-        language,
-    )
-    .append_row([InlineKeyboardButton::callback(
-        get_text!(ctx, language, "SHARE").unwrap().to_string() + " 🦀🔗",
-        format!("share {}", source.code),
-    )])
+    if is_author {
+        option_keyboard(
+            &source.version,
+            &source.mode,
+            &source.edition,
+            &source.code,
+            language,
+        )
+    } else {
+        InlineKeyboardMarkup::default()
+    }
+    .append_row([CallbackData::Run(source.code)
+        .button(get_text!(ctx, language, "RUN").unwrap().to_string() + " 🦀⚙️")])
+}
+
+/// Returns the options keyboard with the share button, only the share button if the user is not the author
+pub fn share_keyboard(source: SourceCode, is_author: bool, language: &str) -> InlineKeyboardMarkup {
+    let ctx = languages_ctx();
+
+    if is_author {
+        option_keyboard(
+            &source.version,
+            &source.mode,
+            &source.edition,
+            &source.code,
+            language,
+        )
+    } else {
+        InlineKeyboardMarkup::default()
+    }
+    .append_row([CallbackData::Share(source.code)
+        .button(get_text!(ctx, language, "SHARE").unwrap().to_string() + " 🦀🔗")])
 }
 pub fn languages_keyboard(language: &str) -> InlineKeyboardMarkup {
     let ctx = languages_ctx();
@@ -191,10 +413,11 @@ pub fn languages_keyboard(language: &str) -> InlineKeyboardMarkup {
         ctx.get_keys()
             .into_iter()
             .map(|lang: &str| {
-                InlineKeyboardButton::callback(
-                    format!("{}{}", if language == lang { "🌟 " } else { "" }, lang),
-                    format!("change_lang {}", lang.replace(" ", "_")),
-                )
+                CallbackData::ChangeLanguage(lang.to_string()).button(format!(
+                    "{}{}",
+                    if language == lang { "🌟 " } else { "" },
+                    lang
+                ))
             })
             .collect::<Vec<InlineKeyboardButton>>()
             .chunks(2)
@@ -18,41 +18,118 @@
 
 #[macro_use]
 extern crate diesel;
+#[macro_use]
+extern crate diesel_migrations;
 extern crate json_gettext;
 extern crate strfmt;
 
 use dotenv::dotenv;
+use rpg_db::DbConnection;
+use settings::SETTINGS;
+use std::{env, error::Error, net::SocketAddr, process};
 use teloxide::{dispatching2::UpdateFilterExt, prelude2::*, types::Update};
 
+mod admin;
 mod bot;
+mod callback;
+mod errors;
+mod health;
+mod import;
 mod keyboards;
+mod metrics;
+mod migrations;
 mod models;
+mod queue;
 mod rpg;
 mod rpg_db;
+mod scheduler;
 mod schema;
+mod server;
+mod settings;
+mod stats;
+mod webhook;
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
+    let database_url: &str = SETTINGS
+        .database
+        .url
+        .as_deref()
+        .expect("DATABASE_URL must be set");
+    migrate_database(database_url);
+    if env::args().any(|arg| arg == "--migrate-only") {
+        return;
+    }
+    if let Some(sqlite_path) = env::args()
+        .skip_while(|arg| arg != "--import-sqlite")
+        .nth(1)
+    {
+        if let Err(err) = import::import_sqlite(&sqlite_path, database_url) {
+            eprintln!("Cannot import the SQLite database: {}", err);
+            process::exit(1);
+        }
+        return;
+    }
     run().await;
 }
 
+/// Apply the pending migrations of the database, exit if it cannot be migrated
+fn migrate_database(database_url: &str) {
+    if let Err(err) = DbConnection::establish(database_url)
+        .map_err(Box::<dyn Error>::from)
+        .and_then(|mut conn| migrations::run(&mut conn))
+    {
+        eprintln!("Cannot migrate the database: {}", err);
+        process::exit(1);
+    }
+}
+
 async fn run() {
     teloxide::enable_logging!();
-    let bot = Bot::from_env().auto_send();
+    let bot = Bot::with_client(
+        SETTINGS
+            .telegram
+            .token
+            .as_deref()
+            .expect("TELOXIDE_TOKEN must be set"),
+        teloxide::net::client_from_env(),
+    )
+    .auto_send();
+
+    let pool: rpg_db::DbPool = rpg_db::establish_pool();
+    tokio::spawn(scheduler::run(pool.clone()));
+
+    if let Some(address) = &SETTINGS.server.http_address {
+        // the HTTP server (metrics and health check) is optional, enabled only if `HTTP_ADDRESS` is set
+        let address: SocketAddr = address
+            .parse()
+            .expect("HTTP_ADDRESS should be socket address like `127.0.0.1:8080`");
+        tokio::spawn(server::serve(address, pool.clone()));
+    }
 
+    let username: bot::BotUsername = match bot::bot_username(&bot).await {
+        Ok(username) => username,
+        Err(err) => {
+            eprintln!("Cannot get the bot username: {}", err);
+            process::exit(1);
+        }
+    };
     log::info!(
         "Starting Rust Playground Bot in https://t.me/{}",
-        bot::bot_username(&bot).await
+        username.0
     );
 
     let handler = dptree::entry()
         // Message branches
         .branch(Update::filter_message().branch(
             dptree::filter(|message: Message| message.text().is_some()).endpoint(
-                |message: Message, bot: AutoSend<Bot>| async move {
+                |message: Message,
+                 bot: AutoSend<Bot>,
+                 pool: rpg_db::DbPool,
+                 username: bot::BotUsername| async move {
                     || -> Result<(), ()> {
-                        tokio::spawn(bot::message_text_handler(message, bot));
+                        tokio::spawn(bot::message_text_handler(message, bot, pool, username));
                         Ok(())
                     }()
                 },
@@ -60,17 +137,28 @@ async fn run() {
         ))
         // Callback query branch
         .branch(Update::filter_callback_query().endpoint(
-            |bot: AutoSend<Bot>, callback_query: CallbackQuery| async move {
+            |bot: AutoSend<Bot>, callback_query: CallbackQuery, pool: rpg_db::DbPool| async move {
                 || -> Result<(), ()> {
-                    tokio::spawn(bot::callback_handler(bot, callback_query));
+                    tokio::spawn(bot::callback_handler(bot, callback_query, pool));
                     Ok(())
                 }()
             },
         ));
 
-    Dispatcher::builder(bot, handler)
-        .build()
-        .setup_ctrlc_handler()
-        .dispatch()
-        .await;
+    let mut dispatcher = Dispatcher::builder(bot.clone(), handler)
+        .dependencies(dptree::deps![pool, username])
+        .build();
+    dispatcher.setup_ctrlc_handler();
+    match webhook::WebhookConfig::from_settings() {
+        Some(config) => {
+            dispatcher
+                .dispatch_with_listener(
+                    webhook::listener(&bot, config).await,
+                    LoggingErrorHandler::with_custom_text("An error from the webhook"),
+                )
+                .await
+        }
+        // long polling if the webhook is not configured
+        None => dispatcher.dispatch().await,
+    }
 }
@@ -0,0 +1,125 @@
+// rpg_bot - Telegram bot 🤖, help you to run and share Rust code in Telegram via Rust playground 🦀
+// Source code: <https://github.com/TheAwiteb/rpg_bot>
+//
+// Copyright (C) 2022 TheAwiteb <awiteb@hotmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::models::DieselResult;
+use hyper::{Body, Response};
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge,
+    Encoder, HistogramVec, IntCounter, IntCounterVec, IntGauge, TextEncoder,
+};
+
+lazy_static! {
+    /// Number of the received commands, by command
+    pub static ref COMMANDS: IntCounterVec = register_int_counter_vec!(
+        "rpg_bot_commands_total",
+        "Number of the received commands",
+        &["command"]
+    )
+    .unwrap();
+    /// Number of the button clicks, by callback command
+    pub static ref CALLBACKS: IntCounterVec = register_int_counter_vec!(
+        "rpg_bot_callbacks_total",
+        "Number of the button clicks",
+        &["command"]
+    )
+    .unwrap();
+    /// Number of the run/share requests, by kind and outcome (`success`, `failure` or `cached`)
+    pub static ref PLAYGROUND_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "rpg_bot_playground_requests_total",
+        "Number of the Rust playground requests",
+        &["kind", "outcome"]
+    )
+    .unwrap();
+    /// Rust playground requests latency in seconds, by kind (cached results are not included)
+    pub static ref PLAYGROUND_LATENCY: HistogramVec = register_histogram_vec!(
+        "rpg_bot_playground_latency_seconds",
+        "Rust playground requests latency",
+        &["kind"],
+        vec![0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 20.0, 30.0, 60.0]
+    )
+    .unwrap();
+    /// Number of the rejected commands and clicks, by reason (`command_delay`, `button_delay` or `attempts`)
+    pub static ref RATE_LIMITED: IntCounterVec = register_int_counter_vec!(
+        "rpg_bot_rate_limited_total",
+        "Number of the rejected commands and button clicks",
+        &["reason"]
+    )
+    .unwrap();
+    /// Number of the database errors
+    pub static ref DB_ERRORS: IntCounter =
+        register_int_counter!("rpg_bot_db_errors_total", "Number of the database errors").unwrap();
+    /// Number of the run/share requests that wait in the queue
+    pub static ref QUEUE_WAITING: IntGauge = register_int_gauge!(
+        "rpg_bot_queue_waiting",
+        "Number of the Rust playground requests that wait in the queue"
+    )
+    .unwrap();
+    /// Number of the running run/share requests
+    pub static ref QUEUE_RUNNING: IntGauge = register_int_gauge!(
+        "rpg_bot_queue_running",
+        "Number of the running Rust playground requests"
+    )
+    .unwrap();
+    /// `1` if the circuit breaker of the Rust playground is open (the requests are not sent)
+    pub static ref PLAYGROUND_CIRCUIT_OPEN: IntGauge = register_int_gauge!(
+        "rpg_bot_playground_circuit_open",
+        "Whether the Rust playground circuit breaker is open"
+    )
+    .unwrap();
+}
+
+/// Register all metrics, so they are exported before their first use
+pub fn register() {
+    lazy_static::initialize(&COMMANDS);
+    lazy_static::initialize(&CALLBACKS);
+    lazy_static::initialize(&PLAYGROUND_REQUESTS);
+    lazy_static::initialize(&PLAYGROUND_LATENCY);
+    lazy_static::initialize(&RATE_LIMITED);
+    lazy_static::initialize(&DB_ERRORS);
+    lazy_static::initialize(&QUEUE_WAITING);
+    lazy_static::initialize(&QUEUE_RUNNING);
+    lazy_static::initialize(&PLAYGROUND_CIRCUIT_OPEN);
+}
+
+/// Log and count the database errors
+pub trait LogDbError {
+    fn log_db_error(self);
+}
+
+impl<T> LogDbError for DieselResult<T> {
+    fn log_db_error(self) {
+        if let Err(err) = self {
+            DB_ERRORS.inc();
+            log::error!("Database error: {:?}", err);
+        }
+    }
+}
+
+/// Returns `GET /metrics` response, the metrics in prometheus text format
+pub fn response() -> Response<Body> {
+    let mut buffer: Vec<u8> = Vec::new();
+    let encoder = TextEncoder::new();
+    encoder
+        .encode(&prometheus::gather(), &mut buffer)
+        .expect("Cannot encode the metrics");
+    Response::builder()
+        .header(hyper::header::CONTENT_TYPE, encoder.format_type())
+        .body(Body::from(buffer))
+        .unwrap()
+}
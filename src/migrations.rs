@@ -0,0 +1,87 @@
+// rpg_bot - Telegram bot 🤖, help you to run and share Rust code in Telegram via Rust playground 🦀
+// Source code: <https://github.com/TheAwiteb/rpg_bot>
+//
+// Copyright (C) 2022 TheAwiteb <awiteb@hotmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! The migrations are embedded in the binary, and the pending ones are applied at startup
+
+use crate::{db, rpg_db::DbConnection};
+use diesel_migrations::{setup_database, MigrationConnection};
+use std::{collections::HashSet, error::Error, io};
+
+mod sqlite {
+    embed_migrations!("migrations");
+    pub(super) use self::embedded_migrations::run_with_output;
+}
+
+mod postgres {
+    embed_migrations!("migrations_postgres");
+    pub(super) use self::embedded_migrations::run_with_output;
+}
+
+/// Returns the versions of the embedded migrations of the connection backend
+fn embedded_versions(conn: &DbConnection) -> HashSet<&'static str> {
+    match conn {
+        DbConnection::Sqlite(_) => env!("SQLITE_MIGRATIONS"),
+        DbConnection::Postgres(_) => env!("POSTGRES_MIGRATIONS"),
+    }
+    .split(',')
+    .filter(|version| !version.is_empty())
+    .collect()
+}
+
+/// Returns the versions of the applied migrations
+fn applied_versions(conn: &mut DbConnection) -> diesel::QueryResult<HashSet<String>> {
+    db!(conn, {
+        setup_database(conn)?;
+        conn.previously_run_migration_versions()
+    })
+}
+
+/// Returns the versions of the embedded migrations that are not applied yet, sorted
+pub fn pending(conn: &mut DbConnection) -> diesel::QueryResult<Vec<&'static str>> {
+    let applied: HashSet<String> = applied_versions(conn)?;
+    let mut pending: Vec<&'static str> = embedded_versions(conn)
+        .into_iter()
+        .filter(|version| !applied.contains(*version))
+        .collect();
+    pending.sort_unstable();
+    Ok(pending)
+}
+
+/// Apply the pending migrations, refuse if the database has migrations that the binary doesn't know
+/// (the database schema is newer than the binary)
+pub fn run(conn: &mut DbConnection) -> Result<(), Box<dyn Error>> {
+    let embedded: HashSet<&str> = embedded_versions(conn);
+    let mut unknown: Vec<String> = applied_versions(conn)?
+        .into_iter()
+        .filter(|version| !embedded.contains(version.as_str()))
+        .collect();
+    if !unknown.is_empty() {
+        unknown.sort_unstable();
+        return Err(format!(
+            "The database schema is newer than the bot, unknown migrations: {}",
+            unknown.join(", ")
+        )
+        .into());
+    }
+
+    match conn {
+        DbConnection::Sqlite(conn) => sqlite::run_with_output(conn, &mut io::stdout())?,
+        DbConnection::Postgres(conn) => postgres::run_with_output(conn, &mut io::stdout())?,
+    }
+    Ok(())
+}
@@ -17,16 +17,23 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
 use super::{
+    db,
+    errors::{RpgError, RpgResult},
     rpg::Code,
-    schema::{config, source_codes, users},
+    rpg_db::DbConnection,
+    schema::{cached_results, config, events, moderation_logs, snippets, source_codes, users},
+    settings::SETTINGS,
 };
-use chrono::{offset, NaiveDateTime};
-use diesel::{prelude::*, query_builder::UpdateStatement, update};
+use chrono::{offset, Duration, NaiveDateTime};
+use diesel::{prelude::*, update};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use teloxide::types::User as TelegramUser;
 
-#[derive(Queryable)]
+sql_function!(fn lower(x: diesel::sql_types::Nullable<diesel::sql_types::Text>) -> diesel::sql_types::Nullable<diesel::sql_types::Text>);
+
+#[derive(Queryable, Insertable)]
+#[table_name = "users"]
 pub struct Users {
     pub id: i32,
     pub username: Option<String>,
@@ -37,9 +44,24 @@ pub struct Users {
     pub attempts_maximum: i32,
     pub last_command_record: Option<NaiveDateTime>,
     pub last_button_record: Option<NaiveDateTime>,
+    pub is_admin: bool,
+    pub is_banned: bool,
+    pub is_active: bool,
+    /// Overrides `command_delay` config if set
+    pub command_delay: Option<i32>,
+    /// Overrides `button_delay` config if set
+    pub button_delay: Option<i32>,
+    /// The first attempt time in the current attempts window
+    pub attempts_window_start: Option<NaiveDateTime>,
+    /// Shadow banned users are ignored silently
+    pub is_shadow_banned: bool,
+    pub ban_reason: Option<String>,
+    /// The ban expiry, `None` means forever
+    pub banned_until: Option<NaiveDateTime>,
 }
 
-#[derive(Debug, Queryable)]
+#[derive(Debug, Queryable, Insertable)]
+#[table_name = "source_codes"]
 pub struct SourceCode {
     pub id: i32,
     pub user_id: i32,
@@ -49,9 +71,34 @@ pub struct SourceCode {
     pub edition: String,
     pub mode: String,
     pub created_at: NaiveDateTime,
+    pub is_public: bool,
+    pub hash: Option<String>,
+}
+
+#[derive(Debug, Queryable, QueryableByName, Insertable)]
+#[table_name = "snippets"]
+pub struct Snippet {
+    pub id: i32,
+    pub user_id: i32,
+    pub source_code_id: i32,
+    pub name: String,
+    pub tags: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Queryable, Insertable)]
+#[table_name = "cached_results"]
+#[allow(dead_code)]
+pub struct CachedResult {
+    pub id: i32,
+    pub hash: String,
+    pub kind: String,
+    pub output: String,
+    pub created_at: NaiveDateTime,
 }
 
-#[derive(Queryable)]
+#[derive(Queryable, Insertable)]
+#[table_name = "config"]
 pub struct Config {
     pub id: i32,
     pub name: String,
@@ -64,6 +111,23 @@ pub struct NewUser {
     pub username: Option<String>,
     pub telegram_id: String,
     pub telegram_fullname: String,
+    pub language: String,
+}
+
+/// Statistics of the events, see [`Event::stats`]
+pub struct EventStats {
+    /// The number of the events by `(kind, is_cached, success)`
+    pub counts: Vec<(String, bool, bool, i64)>,
+    pub active_users: i64,
+    /// The number of the distinct `(user, day)` pairs
+    pub user_days: i64,
+    pub first_event: Option<NaiveDateTime>,
+    /// The most used versions with their count
+    pub versions: Vec<(String, i64)>,
+    /// The most used editions with their count
+    pub editions: Vec<(String, i64)>,
+    /// The durations at the percentiles, `None` if there are no durations
+    pub percentiles: Vec<Option<i32>>,
 }
 
 #[derive(Debug, Insertable)]
@@ -76,6 +140,91 @@ pub struct NewSourceCode {
     pub edition: String,
     pub mode: String,
     pub created_at: NaiveDateTime,
+    pub hash: String,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "snippets"]
+pub struct NewSnippet {
+    pub user_id: i32,
+    pub source_code_id: i32,
+    pub name: String,
+    pub tags: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// Usage event (run, share or callback)
+#[allow(dead_code)]
+#[derive(Debug, Queryable, Insertable)]
+#[table_name = "events"]
+pub struct Event {
+    pub id: i32,
+    pub user_id: i32,
+    pub chat_id: i64,
+    /// `run`, `share` or `callback`
+    pub kind: String,
+    /// The command name, or the callback command
+    pub command: String,
+    pub version: Option<String>,
+    pub mode: Option<String>,
+    pub edition: Option<String>,
+    /// Rust playground request duration in milliseconds
+    pub duration: Option<i32>,
+    pub success: bool,
+    pub output_size: i32,
+    pub is_cached: bool,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "events"]
+pub struct NewEvent {
+    pub user_id: i32,
+    pub chat_id: i64,
+    pub kind: String,
+    pub command: String,
+    pub version: Option<String>,
+    pub mode: Option<String>,
+    pub edition: Option<String>,
+    pub duration: Option<i32>,
+    pub success: bool,
+    pub output_size: i32,
+    pub is_cached: bool,
+    pub created_at: NaiveDateTime,
+}
+
+/// Moderation action (ban, shadowban and unban) made by admin
+#[allow(dead_code)]
+#[derive(Debug, Queryable, Insertable)]
+#[table_name = "moderation_logs"]
+pub struct ModerationLog {
+    pub id: i32,
+    pub admin_id: i32,
+    pub user_id: i32,
+    pub action: String,
+    pub reason: Option<String>,
+    pub until: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "moderation_logs"]
+pub struct NewModerationLog {
+    pub admin_id: i32,
+    pub user_id: i32,
+    pub action: String,
+    pub reason: Option<String>,
+    pub until: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "cached_results"]
+pub struct NewCachedResult {
+    pub hash: String,
+    pub kind: String,
+    pub output: String,
+    pub created_at: NaiveDateTime,
 }
 
 #[derive(Debug, Insertable)]
@@ -85,34 +234,149 @@ pub struct NewConfig {
     pub value: String,
 }
 
+/// Config that the bot uses, with its default value and the allowed range of its value
 #[derive(Debug)]
-pub enum RpgError {
-    Diesel(DieselError),
-    Text(String),
+pub struct KnownConfig {
+    pub name: &'static str,
+    pub default: i64,
+    pub minimum: i64,
+    pub maximum: i64,
+    /// The step of the edit keyboard buttons
+    pub step: i64,
 }
 
+/// All configs that the bot uses, all of them are integers
+pub const KNOWN_CONFIGS: [KnownConfig; 12] = [
+    KnownConfig {
+        // The attempts will be reset after this window (from the first attempt), `0` means never
+        name: "attempts_window",
+        // One day
+        default: 60 * 60 * 24,
+        minimum: 0,
+        maximum: 60 * 60 * 24 * 30,
+        step: 60 * 60,
+    },
+    KnownConfig {
+        name: "button_delay",
+        default: 2,
+        minimum: 0,
+        maximum: 60 * 60,
+        step: 1,
+    },
+    KnownConfig {
+        name: "code_length",
+        default: 4,
+        minimum: 3,
+        maximum: 16,
+        step: 1,
+    },
+    KnownConfig {
+        name: "command_delay",
+        default: 15,
+        minimum: 0,
+        maximum: 60 * 60,
+        step: 5,
+    },
+    KnownConfig {
+        // The events (used by `/stats`) older than this are deleted, `0` means they are kept forever
+        name: "events_retention",
+        // 90 days
+        default: 60 * 60 * 24 * 90,
+        minimum: 0,
+        maximum: 60 * 60 * 24 * 365 * 5,
+        step: 60 * 60 * 24,
+    },
+    KnownConfig {
+        // Interval of the old events removing job, `0` means disabled
+        name: "events_retention_interval",
+        // One day
+        default: 60 * 60 * 24,
+        minimum: 0,
+        maximum: 60 * 60 * 24 * 30,
+        step: 60 * 60,
+    },
+    KnownConfig {
+        // Interval of the expired source codes and cached results removing job, `0` means disabled
+        name: "expire_interval",
+        // Ten minutes
+        default: 60 * 10,
+        minimum: 0,
+        maximum: 60 * 60 * 24,
+        step: 60,
+    },
+    KnownConfig {
+        // Interval of the attempts reset job (of the users whose attempts window is over)
+        name: "reset_attempts_interval",
+        // Five minutes
+        default: 60 * 5,
+        minimum: 0,
+        maximum: 60 * 60 * 24,
+        step: 60,
+    },
+    KnownConfig {
+        name: "result_cache_ttl",
+        // One hour
+        default: 60 * 60,
+        minimum: 0,
+        maximum: 60 * 60 * 24 * 30,
+        step: 60 * 10,
+    },
+    KnownConfig {
+        // Interval of the statistics rollup job, `0` means the statistics are computed on every `/stats`
+        name: "stats_rollup_interval",
+        // Five minutes
+        default: 60 * 5,
+        minimum: 0,
+        maximum: 60 * 60 * 24,
+        step: 60,
+    },
+    KnownConfig {
+        name: "time_limit_expiration",
+        // One week
+        default: 60 * 60 * 24 * 7,
+        minimum: 60 * 60,
+        maximum: 60 * 60 * 24 * 365,
+        step: 60 * 60 * 24,
+    },
+    KnownConfig {
+        // Interval of the database VACUUM job, `0` means disabled
+        name: "vacuum_interval",
+        // One week
+        default: 60 * 60 * 24 * 7,
+        minimum: 0,
+        maximum: 60 * 60 * 24 * 30,
+        step: 60 * 60 * 24,
+    },
+];
+
 pub type DieselError = diesel::result::Error;
 pub type DieselResult<T> = Result<T, DieselError>;
 
-impl TryFrom<(&NewSourceCode, &mut SqliteConnection)> for SourceCode {
+impl TryFrom<(&NewSourceCode, &mut DbConnection)> for SourceCode {
     type Error = DieselError;
 
-    fn try_from((source, conn): (&NewSourceCode, &mut SqliteConnection)) -> DieselResult<Self> {
+    fn try_from((source, conn): (&NewSourceCode, &mut DbConnection)) -> DieselResult<Self> {
         use super::schema::source_codes::dsl::{code, source_codes};
-        source_codes
-            .filter(code.eq(source.code.clone()))
-            .first::<Self>(conn)
+        db!(
+            conn,
+            source_codes
+                .filter(code.eq(source.code.clone()))
+                .first::<Self>(conn)
+        )
     }
 }
 
-impl TryFrom<(&NewUser, &mut SqliteConnection)> for Users {
+impl TryFrom<(&NewUser, &mut DbConnection)> for Users {
     type Error = DieselError;
 
-    fn try_from((user, conn): (&NewUser, &mut SqliteConnection)) -> DieselResult<Self> {
+    fn try_from((user, conn): (&NewUser, &mut DbConnection)) -> DieselResult<Self> {
         use super::schema::users::dsl::{telegram_id, users};
-        users
-            .filter(telegram_id.eq(&user.telegram_id))
-            .first::<Self>(conn)
+        db!(
+            conn,
+            users
+                .filter(telegram_id.eq(&user.telegram_id))
+                .first::<Self>(conn)
+        )
     }
 }
 
@@ -126,6 +390,16 @@ impl From<&TelegramUser> for NewUser {
 impl From<SourceCode> for NewSourceCode {
     fn from(source: SourceCode) -> Self {
         Self {
+            // old source codes don't have a hash
+            hash: source.hash.unwrap_or_else(|| {
+                Code::new(
+                    &source.source_code,
+                    &source.version,
+                    &source.mode,
+                    &source.edition,
+                )
+                .hash()
+            }),
             user_id: source.user_id,
             code: source.code,
             source_code: source.source_code,
@@ -146,52 +420,117 @@ impl NewConfig {
     }
 }
 
+impl KnownConfig {
+    /// Returns the known config with `name`
+    pub fn get(name: &str) -> Option<&'static Self> {
+        KNOWN_CONFIGS.iter().find(|known| known.name == name)
+    }
+
+    /// Returns the default value of the config, from the settings if it's set there
+    /// (the settings values are validated when they are loaded)
+    pub fn default_value(&self) -> i64 {
+        SETTINGS.limits.get(self.name).unwrap_or(self.default)
+    }
+
+    /// Returns the value if it's valid for the config
+    pub fn validate(&self, value: &str) -> RpgResult<i64> {
+        match value.parse::<i64>() {
+            Ok(value) if (self.minimum..=self.maximum).contains(&value) => Ok(value),
+            _ => Err(RpgError::Validation(format!(
+                "`{}` should be integer between {} and {}",
+                self.name, self.minimum, self.maximum
+            ))),
+        }
+    }
+}
+
 impl Config {
     /// Get config by name
-    pub fn get_by_name(name: &str, conn: &mut SqliteConnection) -> Option<Self> {
+    pub fn get_by_name(name: &str, conn: &mut DbConnection) -> Option<Self> {
         use super::schema::config::dsl::{config, name as name_};
-        config.filter(name_.eq(name)).first::<Self>(conn).ok()
+        db!(conn, config.filter(name_.eq(name)).first::<Self>(conn)).ok()
+    }
+
+    /// Returns the value of known config, the default value will be used if the config is
+    /// not exist or invalid
+    pub fn integer(name: &str, conn: &mut DbConnection) -> i64 {
+        let known: &KnownConfig =
+            KnownConfig::get(name).unwrap_or_else(|| panic!("`{}` is not known config", name));
+        Config::value_of(known, conn)
+    }
+
+    /// Returns the value of the known config, the database overrides the settings
+    fn value_of(known: &KnownConfig, conn: &mut DbConnection) -> i64 {
+        match Config::get_by_name(known.name, conn) {
+            Some(config) => known.validate(&config.value).unwrap_or_else(|err| {
+                log::warn!("{:?}, the default value will be used", err);
+                known.default_value()
+            }),
+            None => known.default_value(),
+        }
     }
 
-    /// Returns config with `name` if exist or add new to db with `name` and `value`
-    pub fn get_or_add(name: &str, value: &str, conn: &mut SqliteConnection) -> Self {
-        Config::get_by_name(name, conn).unwrap_or_else(|| Config::add(name, value, conn).unwrap())
+    /// Returns all known configs, with their values
+    pub fn all(conn: &mut DbConnection) -> Vec<(&'static KnownConfig, i64)> {
+        KNOWN_CONFIGS
+            .iter()
+            .map(|known| (known, Config::value_of(known, conn)))
+            .collect()
+    }
+
+    /// Set known config value after validate it, add it if not exist
+    pub fn set(name: &str, value: &str, conn: &mut DbConnection) -> RpgResult<Self> {
+        use super::schema::config::dsl::{config, name as name_, value as value_};
+        let value: String = KnownConfig::get(name)
+            .ok_or_else(|| RpgError::Validation(format!("`{}` is not known config", name)))?
+            .validate(value)?
+            .to_string();
+
+        if Config::get_by_name(name, conn).is_some() {
+            db!(
+                conn,
+                update(config.filter(name_.eq(name)))
+                    .set(value_.eq(&value))
+                    .execute(conn)
+            )?;
+            Ok(Config::get_by_name(name, conn).unwrap())
+        } else {
+            Ok(Config::add(name, &value, conn)?)
+        }
     }
 
     /// Add new config to db
-    pub fn add(name: &str, value: &str, conn: &mut SqliteConnection) -> DieselResult<Self> {
-        diesel::insert_into(config::table)
-            .values(NewConfig::new(name, value))
-            .execute(conn)?;
+    pub fn add(name: &str, value: &str, conn: &mut DbConnection) -> DieselResult<Self> {
+        db!(
+            conn,
+            diesel::insert_into(config::table)
+                .values(NewConfig::new(name, value))
+                .execute(conn)
+        )?;
         Ok(Config::get_by_name(name, conn).unwrap())
     }
 }
 
 impl SourceCode {
     /// Returns `true` if the code are exist in database
-    pub fn code_is_exist(conn: &mut SqliteConnection, code: &str) -> bool {
+    pub fn code_is_exist(conn: &mut DbConnection, code: &str) -> bool {
         use super::schema::source_codes::dsl::{code as code_, source_codes};
-        source_codes
-            .filter(code_.eq(&code))
-            .first::<Self>(conn)
-            .ok()
-            .is_some()
+        db!(
+            conn,
+            source_codes.filter(code_.eq(&code)).first::<Self>(conn)
+        )
+        .ok()
+        .is_some()
     }
 
     /// Returns new code that not in database
     /// The code is a distinctive code that distinguishes the source code from others, (it is used to request it instead of using id)
-    pub fn code(conn: &mut SqliteConnection) -> DieselResult<String> {
+    pub fn code(conn: &mut DbConnection) -> DieselResult<String> {
         loop {
             // create random code
             let code: String = thread_rng()
                 .sample_iter(&Alphanumeric)
-                .take(
-                    // default value is 4
-                    Config::get_or_add("code_length", "4", conn)
-                        .value
-                        .parse::<usize>()
-                        .expect("`code_length` config should be integer"),
-                )
+                .take(Config::integer("code_length", conn) as usize)
                 .map(char::from)
                 .collect::<String>()
                 .to_ascii_lowercase();
@@ -202,70 +541,128 @@ impl SourceCode {
         }
     }
 
-    /// Use this function to remove all source codes that have expired
-    pub fn filter_source_codes(conn: &mut SqliteConnection) -> DieselResult<()> {
-        use super::schema::source_codes::dsl::{created_at, source_codes};
+    /// Use this function to remove all source codes that have expired (saved snippets never expire),
+    /// returns the number of the removed source codes
+    pub fn filter_source_codes(conn: &mut DbConnection) -> DieselResult<usize> {
+        use super::schema::snippets::dsl::{snippets, source_code_id};
+        use super::schema::source_codes::dsl::{created_at, id, source_codes};
 
-        // default value is one week
-        let time_limit_expiration: i64 = Config::get_or_add(
-            "time_limit_expiration",
-            &(((60 * 60/* One houre */) * 24/* One day */) * 7/* One week */).to_string(),
+        let time_limit_expiration: i64 = Config::integer("time_limit_expiration", conn); // in seconds
+        db!(
             conn,
+            diesel::delete(
+                source_codes
+                    .filter(created_at.le(NaiveDateTime::from_timestamp(
+                        offset::Utc::now().timestamp() - time_limit_expiration,
+                        0,
+                    )))
+                    .filter(diesel::dsl::not(id.eq_any(snippets.select(source_code_id)))),
+            )
+            .execute(conn)
         )
-        .value
-        .parse::<i64>()
-        .expect("`time_limit_expiration` config should be integer"); // in seconds
-        diesel::delete(
-            source_codes.filter(created_at.le(NaiveDateTime::from_timestamp(
-                offset::Utc::now().timestamp() - time_limit_expiration,
-                0,
-            ))),
-        )
-        .execute(conn)?;
-        Ok(())
     }
 
     /// Returns source code by code
-    pub fn get_by_code(code: &str, conn: &mut SqliteConnection) -> DieselResult<Self> {
+    pub fn get_by_code(code: &str, conn: &mut DbConnection) -> DieselResult<Self> {
         use super::schema::source_codes::dsl::{code as code_, source_codes};
-        source_codes.filter(code_.eq(code)).first::<Self>(conn)
+        db!(
+            conn,
+            source_codes.filter(code_.eq(code)).first::<Self>(conn)
+        )
+    }
+
+    /// Returns the user source code that have the given content hash
+    pub fn get_by_hash(
+        author_id: i32,
+        content_hash: &str,
+        conn: &mut DbConnection,
+    ) -> DieselResult<Self> {
+        use super::schema::source_codes::dsl::{hash, source_codes, user_id};
+        db!(
+            conn,
+            source_codes
+                .filter(user_id.eq(author_id))
+                .filter(hash.eq(content_hash))
+                .first::<Self>(conn)
+        )
     }
 
     /// Returns source author
-    pub fn author(&self, conn: &mut SqliteConnection) -> DieselResult<Users> {
+    pub fn author(&self, conn: &mut DbConnection) -> DieselResult<Users> {
         use super::schema::users::dsl::{id, users};
-        users.filter(id.eq(&self.user_id)).first::<Users>(conn)
+        db!(
+            conn,
+            users.filter(id.eq(&self.user_id)).first::<Users>(conn)
+        )
+    }
+
+    /// Returns `true` if the user is the author of the source code
+    pub fn is_author(&self, user: &Users) -> bool {
+        self.user_id == user.id
+    }
+
+    /// Returns `true` if the user can see the source code (public source or his author)
+    pub fn can_view(&self, user: &Users) -> bool {
+        self.is_public || self.is_author(user)
+    }
+
+    /// Toggle source code visibility (private/public)
+    pub fn toggle_visibility(&mut self, conn: &mut DbConnection) -> DieselResult<()> {
+        use super::schema::source_codes::dsl::{is_public, source_codes};
+        db!(
+            conn,
+            update(source_codes.find(self.id))
+                .set(is_public.eq(!self.is_public))
+                .execute(conn)
+        )?;
+        self.is_public = !self.is_public;
+        Ok(())
     }
 
     // Update field by name, just can update `version`, `edition`, `mode`
+    // If the author have the same source code with the new options, it will be used instead
     pub fn update_by_name(
         &mut self,
         field_name: &str,
         new_value: &str,
-        conn: &mut SqliteConnection,
-    ) -> Result<(), RpgError> {
-        use super::schema::source_codes::dsl::{edition, mode, source_codes, version};
+        conn: &mut DbConnection,
+    ) -> RpgResult<()> {
+        use super::schema::source_codes::dsl::{edition, hash, mode, source_codes, version};
 
         if ["version", "edition", "mode"].contains(&field_name) {
-            let update_statement: UpdateStatement<_, _> = update(source_codes.find(self.id));
+            let mut code: Code =
+                Code::new(&self.source_code, &self.version, &self.mode, &self.edition);
             match field_name {
-                "version" => {
-                    self.version = new_value.into();
-                    update_statement.set(version.eq(new_value)).execute(conn)
-                }
-                "edition" => {
-                    self.edition = new_value.into();
-                    update_statement.set(edition.eq(new_value)).execute(conn)
-                }
-                _ => {
-                    self.mode = new_value.into();
-                    update_statement.set(mode.eq(new_value)).execute(conn)
-                }
+                "version" => code.version = new_value.into(),
+                "edition" => code.edition = new_value.into(),
+                _ => code.mode = new_value.into(),
+            };
+            code.is_valid()?;
+            let new_hash: String = code.hash();
+
+            if let Ok(source) = SourceCode::get_by_hash(self.user_id, &new_hash, conn) {
+                *self = source;
+                return Ok(());
             }
-            .map_err(RpgError::Diesel)?;
+
+            db!(
+                conn,
+                update(source_codes.find(self.id))
+                    .set((
+                        version.eq(&code.version),
+                        edition.eq(&code.edition),
+                        mode.eq(&code.mode),
+                        hash.eq(&new_hash),
+                    ))
+                    .execute(conn)
+            )?;
+            self.version = code.version;
+            self.edition = code.edition;
+            self.mode = code.mode;
+            self.hash = Some(new_hash);
             Ok(())
         } else {
-            Err(RpgError::Text(format!(
+            Err(RpgError::Validation(format!(
                 "Cannot update {} field",
                 field_name
             )))
@@ -273,12 +670,431 @@ impl SourceCode {
     }
 }
 
+impl CachedResult {
+    /// Returns the time to live of the cached results in seconds, `0` means the cache is disabled
+    pub fn ttl(conn: &mut DbConnection) -> i64 {
+        Config::integer("result_cache_ttl", conn)
+    }
+
+    /// Returns the cached result of the content hash and kind (`run` or `share`) if not expired
+    pub fn get(content_hash: &str, result_kind: &str, conn: &mut DbConnection) -> Option<Self> {
+        use super::schema::cached_results::dsl::{cached_results, created_at, hash, kind};
+        let ttl: i64 = Self::ttl(conn);
+        if ttl <= 0 {
+            return None;
+        }
+        db!(
+            conn,
+            cached_results
+                .filter(hash.eq(content_hash))
+                .filter(kind.eq(result_kind))
+                .filter(created_at.gt(offset::Utc::now().naive_utc() - Duration::seconds(ttl)))
+                .first::<Self>(conn)
+        )
+        .ok()
+    }
+
+    /// Cache the result of the content hash and kind (`run` or `share`), replace the old one if exist
+    pub fn save(
+        content_hash: &str,
+        result_kind: &str,
+        result_output: &str,
+        conn: &mut DbConnection,
+    ) -> DieselResult<()> {
+        use super::schema::cached_results::dsl::{cached_results as cached_results_, hash, kind};
+        db!(
+            conn,
+            diesel::delete(
+                cached_results_
+                    .filter(hash.eq(content_hash))
+                    .filter(kind.eq(result_kind)),
+            )
+            .execute(conn)
+        )?;
+        db!(
+            conn,
+            diesel::insert_into(cached_results::table)
+                .values(NewCachedResult {
+                    hash: content_hash.to_string(),
+                    kind: result_kind.to_string(),
+                    output: result_output.to_string(),
+                    created_at: offset::Utc::now().naive_utc(),
+                })
+                .execute(conn)
+        )?;
+        Ok(())
+    }
+
+    /// Use this function to remove all cached results that have expired,
+    /// returns the number of the removed results
+    pub fn filter_results(conn: &mut DbConnection) -> DieselResult<usize> {
+        use super::schema::cached_results::dsl::{cached_results, created_at};
+        let ttl: i64 = Self::ttl(conn).max(0);
+        db!(
+            conn,
+            diesel::delete(
+                cached_results
+                    .filter(created_at.le(offset::Utc::now().naive_utc() - Duration::seconds(ttl))),
+            )
+            .execute(conn)
+        )
+    }
+}
+
+impl Snippet {
+    /// Returns user snippet by its name
+    pub fn get_by_name(
+        author: &Users,
+        snippet_name: &str,
+        conn: &mut DbConnection,
+    ) -> DieselResult<Self> {
+        use super::schema::snippets::dsl::{name, snippets, user_id};
+        db!(
+            conn,
+            snippets
+                .filter(user_id.eq(author.id))
+                .filter(name.eq(snippet_name))
+                .first::<Self>(conn)
+        )
+    }
+
+    /// Returns the source code of the snippet
+    pub fn source(&self, conn: &mut DbConnection) -> DieselResult<SourceCode> {
+        use super::schema::source_codes::dsl::source_codes;
+        db!(
+            conn,
+            source_codes
+                .find(self.source_code_id)
+                .first::<SourceCode>(conn)
+        )
+    }
+
+    /// Returns snippet tags
+    pub fn tags(&self) -> Vec<&str> {
+        self.tags.split_whitespace().collect()
+    }
+
+    /// Delete the snippet, its source code will expire like other source codes
+    pub fn delete(&self, conn: &mut DbConnection) -> DieselResult<()> {
+        use super::schema::snippets::dsl::snippets;
+        db!(conn, diesel::delete(snippets.find(self.id)).execute(conn))?;
+        Ok(())
+    }
+
+    /// Full-text search in user snippets (name, tags and source code), best match first
+    pub fn search(
+        author: &Users,
+        text: &str,
+        limit: i64,
+        conn: &mut DbConnection,
+    ) -> DieselResult<Vec<Self>> {
+        use diesel::sql_types::{BigInt, Integer, Text};
+
+        match conn {
+            DbConnection::Sqlite(conn) => {
+                // Every word is quoted to escape FTS5 syntax, and searched as a prefix
+                let query: String = text
+                    .split_whitespace()
+                    .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+                    .collect::<Vec<String>>()
+                    .join(" ");
+
+                diesel::sql_query(
+                    "SELECT snippets.* FROM snippets \
+                    JOIN snippets_fts ON snippets_fts.rowid = snippets.id \
+                    WHERE snippets_fts MATCH ? AND snippets.user_id = ? \
+                    ORDER BY snippets_fts.rank LIMIT ?",
+                )
+                .bind::<Text, _>(query)
+                .bind::<Integer, _>(author.id)
+                .bind::<BigInt, _>(limit)
+                .load::<Self>(conn)
+            }
+            DbConnection::Postgres(conn) => {
+                // Every word is quoted to escape tsquery syntax, and searched as a prefix
+                let query: String = text
+                    .split_whitespace()
+                    .map(|word| format!("'{}':*", word.replace('\\', "\\\\").replace('\'', "''")))
+                    .collect::<Vec<String>>()
+                    .join(" & ");
+
+                diesel::sql_query(
+                    "SELECT snippets.* FROM snippets \
+                    JOIN source_codes ON source_codes.id = snippets.source_code_id \
+                    CROSS JOIN to_tsquery('simple', $1) AS query \
+                    WHERE snippets.user_id = $2 AND query @@ to_tsvector('simple', \
+                        snippets.name || ' ' || snippets.tags || ' ' || source_codes.source_code) \
+                    ORDER BY ts_rank(to_tsvector('simple', \
+                        snippets.name || ' ' || snippets.tags || ' ' || source_codes.source_code), query) DESC \
+                    LIMIT $3",
+                )
+                .bind::<Text, _>(query)
+                .bind::<Integer, _>(author.id)
+                .bind::<BigInt, _>(limit)
+                .load::<Self>(conn)
+            }
+        }
+    }
+}
+
+impl NewSnippet {
+    /// Make new object, you can save it in database use save method
+    pub fn new(author: &Users, source: &SourceCode, name: &str, tags: &[&str]) -> Self {
+        Self {
+            user_id: author.id,
+            source_code_id: source.id,
+            name: name.to_string(),
+            tags: tags.join(" "),
+            created_at: offset::Utc::now().naive_utc(),
+        }
+    }
+
+    /// save object in database
+    pub fn save(&self, conn: &mut DbConnection) -> DieselResult<Snippet> {
+        use super::schema::snippets::dsl::{name, snippets as snippets_, user_id};
+        db!(
+            conn,
+            diesel::insert_into(snippets::table)
+                .values(self)
+                .execute(conn)
+        )?;
+        db!(
+            conn,
+            snippets_
+                .filter(user_id.eq(self.user_id))
+                .filter(name.eq(&self.name))
+                .first::<Snippet>(conn)
+        )
+    }
+}
+
+impl NewEvent {
+    /// Make new callback event, you can save it in database use save method
+    pub fn callback(author: &Users, chat_id: i64, command: &str) -> Self {
+        Self {
+            user_id: author.id,
+            chat_id,
+            kind: "callback".into(),
+            command: command.into(),
+            version: None,
+            mode: None,
+            edition: None,
+            duration: None,
+            success: true,
+            output_size: 0,
+            is_cached: false,
+            created_at: offset::Utc::now().naive_utc(),
+        }
+    }
+
+    /// Make new run/share event, the duration is `None` for cached results
+    pub fn run_share(
+        author: &Users,
+        chat_id: i64,
+        code: &NewSourceCode,
+        command: &str,
+        duration: Option<i32>,
+        output: &RpgResult<String>,
+    ) -> Self {
+        Self {
+            user_id: author.id,
+            chat_id,
+            kind: command.into(),
+            command: command.into(),
+            version: Some(code.version.clone()),
+            mode: Some(code.mode.clone()),
+            edition: Some(code.edition.clone()),
+            duration,
+            success: output.is_ok(),
+            output_size: match output {
+                Ok(output) | Err(RpgError::Validation(output)) => output.len() as i32,
+                // the output of the other errors is not sent to the user
+                Err(_) => 0,
+            },
+            is_cached: duration.is_none(),
+            created_at: offset::Utc::now().naive_utc(),
+        }
+    }
+
+    /// save object in database
+    pub fn save(&self, conn: &mut DbConnection) -> DieselResult<()> {
+        db!(
+            conn,
+            diesel::insert_into(events::table)
+                .values(self)
+                .execute(conn)
+        )?;
+        Ok(())
+    }
+}
+
+impl Event {
+    /// Remove the events that are older than the `events_retention` config,
+    /// returns the number of the removed events
+    pub fn remove_old(conn: &mut DbConnection) -> DieselResult<usize> {
+        use super::schema::events::dsl::{created_at, events};
+        let retention: i64 = Config::integer("events_retention", conn);
+        if retention == 0 {
+            return Ok(0);
+        }
+        db!(
+            conn,
+            diesel::delete(events.filter(
+                created_at.lt(offset::Utc::now().naive_utc() - Duration::seconds(retention)),
+            ),)
+            .execute(conn)
+        )
+    }
+
+    /// Returns the statistics of the events since the time (all events if `None`), computed by the
+    /// database, with the `top` most used versions/editions and the durations at `percentiles`
+    pub fn stats(
+        time: Option<NaiveDateTime>,
+        top: i64,
+        percentiles: &[i64],
+        conn: &mut DbConnection,
+    ) -> DieselResult<EventStats> {
+        use super::schema::events::dsl::{
+            created_at, duration, edition, events, is_cached, kind, success, version,
+        };
+        use diesel::dsl::sql;
+        use diesel::sql_types::BigInt;
+        // `count_star` can't be selected with the grouped columns in diesel 1.4
+        let count_all = || sql::<BigInt>("COUNT(*)");
+
+        // all the events are after the epoch
+        let since: NaiveDateTime = time.unwrap_or_default();
+        db!(conn, {
+            let period = events.filter(created_at.ge(since));
+            let run_share = period.filter(kind.ne("callback"));
+            let durations = run_share
+                .filter(is_cached.eq(false))
+                .filter(duration.is_not_null());
+
+            let durations_count: i64 = durations.count().get_result(conn)?;
+            let percentiles: Vec<Option<i32>> = percentiles
+                .iter()
+                .map(|percentile| {
+                    if durations_count == 0 {
+                        return Ok(None);
+                    }
+                    // nearest rank
+                    let rank: i64 = (percentile * durations_count + 99) / 100;
+                    durations
+                        .select(duration)
+                        .order(duration.asc())
+                        .offset(rank.clamp(1, durations_count) - 1)
+                        .first::<Option<i32>>(conn)
+                })
+                .collect::<DieselResult<_>>()?;
+
+            Ok(EventStats {
+                counts: period
+                    .group_by((kind, is_cached, success))
+                    .select((kind, is_cached, success, count_all()))
+                    .load(conn)?,
+                active_users: period
+                    .select(sql::<BigInt>("COUNT(DISTINCT user_id)"))
+                    .first(conn)?,
+                user_days: period
+                    .select(sql::<BigInt>(
+                        "COUNT(DISTINCT user_id || ':' || date(created_at))",
+                    ))
+                    .first(conn)?,
+                first_event: period.select(diesel::dsl::min(created_at)).first(conn)?,
+                versions: run_share
+                    .filter(version.is_not_null())
+                    .group_by(version)
+                    .select((version, count_all()))
+                    .order((count_all().desc(), version.asc()))
+                    .limit(top)
+                    .load::<(Option<String>, i64)>(conn)?
+                    .into_iter()
+                    .filter_map(|(version_, count)| Some((version_?, count)))
+                    .collect(),
+                editions: run_share
+                    .filter(edition.is_not_null())
+                    .group_by(edition)
+                    .select((edition, count_all()))
+                    .order((count_all().desc(), edition.asc()))
+                    .limit(top)
+                    .load::<(Option<String>, i64)>(conn)?
+                    .into_iter()
+                    .filter_map(|(edition_, count)| Some((edition_?, count)))
+                    .collect(),
+                percentiles,
+            })
+        })
+    }
+}
+
+impl NewModerationLog {
+    /// Make new object, you can save it in database use save method
+    pub fn new(
+        admin: &Users,
+        user: &Users,
+        action: &str,
+        reason: Option<&str>,
+        until: Option<NaiveDateTime>,
+    ) -> Self {
+        Self {
+            admin_id: admin.id,
+            user_id: user.id,
+            action: action.to_string(),
+            reason: reason.map(str::to_string),
+            until,
+            created_at: offset::Utc::now().naive_utc(),
+        }
+    }
+
+    /// save object in database
+    pub fn save(&self, conn: &mut DbConnection) -> DieselResult<()> {
+        db!(
+            conn,
+            diesel::insert_into(moderation_logs::table)
+                .values(self)
+                .execute(conn)
+        )?;
+        Ok(())
+    }
+}
+
+impl ModerationLog {
+    /// Returns the latest moderation actions, of the user if given
+    pub fn latest(
+        user: Option<&Users>,
+        limit: i64,
+        conn: &mut DbConnection,
+    ) -> DieselResult<Vec<Self>> {
+        use super::schema::moderation_logs::dsl::{id, moderation_logs, user_id};
+        db!(conn, {
+            let mut query = moderation_logs.order(id.desc()).limit(limit).into_boxed();
+            if let Some(user) = user {
+                query = query.filter(user_id.eq(user.id));
+            }
+            query.load::<Self>(conn)
+        })
+    }
+
+    /// Returns the admin who made the action
+    pub fn admin(&self, conn: &mut DbConnection) -> DieselResult<Users> {
+        use super::schema::users::dsl::users;
+        db!(conn, users.find(self.admin_id).first::<Users>(conn))
+    }
+
+    /// Returns the user who the action was made on
+    pub fn user(&self, conn: &mut DbConnection) -> DieselResult<Users> {
+        use super::schema::users::dsl::users;
+        db!(conn, users.find(self.user_id).first::<Users>(conn))
+    }
+}
+
 impl Users {
     /// Update user (`username` and `telegram_fullname`)
     pub async fn update(
         &mut self,
         user: &TelegramUser,
-        conn: &mut SqliteConnection,
+        conn: &mut DbConnection,
     ) -> DieselResult<()> {
         if self.username != user.username {
             self.update_username(&user.username, conn)?;
@@ -290,34 +1106,327 @@ impl Users {
         Ok(())
     }
 
-    /// Add attempt to user attempts
-    pub fn make_attempt(&mut self, conn: &mut SqliteConnection) -> DieselResult<()> {
-        use super::schema::users::dsl::{attempts, telegram_id, users};
-        update(users.filter(telegram_id.eq(&self.telegram_id)))
-            .set(attempts.eq(self.attempts + 1))
-            .execute(conn)?;
+    /// Returns user by `@username` or telegram id
+    pub fn find(query: &str, conn: &mut DbConnection) -> DieselResult<Self> {
+        use super::schema::users::dsl::{telegram_id, username, users};
+        if let Some(query_username) = query.strip_prefix('@') {
+            // usernames are case-insensitive
+            db!(
+                conn,
+                users
+                    .filter(lower(username).eq(query_username.to_lowercase()))
+                    .first::<Self>(conn)
+            )
+        } else {
+            db!(
+                conn,
+                users.filter(telegram_id.eq(query)).first::<Self>(conn)
+            )
+        }
+    }
+
+    /// Returns all admins
+    pub fn admins(conn: &mut DbConnection) -> DieselResult<Vec<Self>> {
+        use super::schema::users::dsl::{is_admin, users};
+        db!(conn, users.filter(is_admin.eq(true)).load::<Self>(conn))
+    }
+
+    /// Returns active users (users who didn't block the bot)
+    pub fn active(conn: &mut DbConnection) -> DieselResult<Vec<Self>> {
+        use super::schema::users::dsl::{id, is_active, users};
+        db!(
+            conn,
+            users
+                .filter(is_active.eq(true))
+                .order(id.asc())
+                .load::<Self>(conn)
+        )
+    }
+
+    /// update `is_active`
+    pub fn set_active(&mut self, new_is_active: bool, conn: &mut DbConnection) -> DieselResult<()> {
+        use super::schema::users::dsl::{is_active, users};
+        db!(
+            conn,
+            update(users.find(self.id))
+                .set(is_active.eq(new_is_active))
+                .execute(conn)
+        )?;
+        self.is_active = new_is_active;
+        Ok(())
+    }
+
+    /// Returns user mention, `@username` if exist else the full name
+    pub fn mention(&self) -> String {
+        match &self.username {
+            Some(username) => format!("@{}", username),
+            None => self.telegram_fullname.clone(),
+        }
+    }
+
+    /// update `is_admin`
+    pub fn set_admin(&mut self, new_is_admin: bool, conn: &mut DbConnection) -> DieselResult<()> {
+        use super::schema::users::dsl::{is_admin, users};
+        db!(
+            conn,
+            update(users.find(self.id))
+                .set(is_admin.eq(new_is_admin))
+                .execute(conn)
+        )?;
+        self.is_admin = new_is_admin;
+        Ok(())
+    }
+
+    /// Ban the user, shadow banned users are ignored silently. `until` is the ban expiry
+    pub fn ban(
+        &mut self,
+        is_shadow: bool,
+        reason: Option<&str>,
+        until: Option<NaiveDateTime>,
+        conn: &mut DbConnection,
+    ) -> DieselResult<()> {
+        use super::schema::users::dsl::{
+            ban_reason, banned_until, is_banned, is_shadow_banned, users,
+        };
+        db!(
+            conn,
+            update(users.find(self.id))
+                .set((
+                    is_banned.eq(!is_shadow),
+                    is_shadow_banned.eq(is_shadow),
+                    ban_reason.eq(reason),
+                    banned_until.eq(until),
+                ))
+                .execute(conn)
+        )?;
+        self.is_banned = !is_shadow;
+        self.is_shadow_banned = is_shadow;
+        self.ban_reason = reason.map(str::to_string);
+        self.banned_until = until;
+        Ok(())
+    }
+
+    /// Unban the user (ban and shadow ban)
+    pub fn unban(&mut self, conn: &mut DbConnection) -> DieselResult<()> {
+        use super::schema::users::dsl::{
+            ban_reason, banned_until, is_banned, is_shadow_banned, users,
+        };
+        db!(
+            conn,
+            update(users.find(self.id))
+                .set((
+                    is_banned.eq(false),
+                    is_shadow_banned.eq(false),
+                    ban_reason.eq(None::<String>),
+                    banned_until.eq(None::<NaiveDateTime>),
+                ))
+                .execute(conn)
+        )?;
+        self.is_banned = false;
+        self.is_shadow_banned = false;
+        self.ban_reason = None;
+        self.banned_until = None;
+        Ok(())
+    }
+
+    /// Unban the user if the ban is expired
+    pub fn refresh_ban(&mut self, conn: &mut DbConnection) -> DieselResult<()> {
+        match self.banned_until {
+            Some(until) if until <= offset::Utc::now().naive_utc() => self.unban(conn),
+            _ => Ok(()),
+        }
+    }
+
+    /// update `attempts_maximum`
+    pub fn set_attempts_maximum(
+        &mut self,
+        new_attempts_maximum: i32,
+        conn: &mut DbConnection,
+    ) -> DieselResult<()> {
+        use super::schema::users::dsl::{attempts_maximum, users};
+        db!(
+            conn,
+            update(users.find(self.id))
+                .set(attempts_maximum.eq(new_attempts_maximum))
+                .execute(conn)
+        )?;
+        self.attempts_maximum = new_attempts_maximum;
+        Ok(())
+    }
+
+    /// Returns the user command delay, `command_delay` config if the user doesn't have one
+    pub fn current_command_delay(&self, conn: &mut DbConnection) -> i64 {
+        self.command_delay
+            .map(i64::from)
+            .unwrap_or_else(|| Config::integer("command_delay", conn))
+    }
+
+    /// Returns the user button delay, `button_delay` config if the user doesn't have one
+    pub fn current_button_delay(&self, conn: &mut DbConnection) -> i64 {
+        self.button_delay
+            .map(i64::from)
+            .unwrap_or_else(|| Config::integer("button_delay", conn))
+    }
+
+    /// update `command_delay`, `None` to use the `command_delay` config
+    pub fn set_command_delay(
+        &mut self,
+        new_command_delay: Option<i32>,
+        conn: &mut DbConnection,
+    ) -> DieselResult<()> {
+        use super::schema::users::dsl::{command_delay, users};
+        db!(
+            conn,
+            update(users.find(self.id))
+                .set(command_delay.eq(new_command_delay))
+                .execute(conn)
+        )?;
+        self.command_delay = new_command_delay;
+        Ok(())
+    }
+
+    /// update `button_delay`, `None` to use the `button_delay` config
+    pub fn set_button_delay(
+        &mut self,
+        new_button_delay: Option<i32>,
+        conn: &mut DbConnection,
+    ) -> DieselResult<()> {
+        use super::schema::users::dsl::{button_delay, users};
+        db!(
+            conn,
+            update(users.find(self.id))
+                .set(button_delay.eq(new_button_delay))
+                .execute(conn)
+        )?;
+        self.button_delay = new_button_delay;
+        Ok(())
+    }
+
+    /// Reset the user attempts (with the attempts window) and command/button records
+    pub fn reset_counters(&mut self, conn: &mut DbConnection) -> DieselResult<()> {
+        use super::schema::users::dsl::{
+            attempts, attempts_window_start, last_button_record, last_command_record, users,
+        };
+        db!(
+            conn,
+            update(users.find(self.id))
+                .set((
+                    attempts.eq(0),
+                    attempts_window_start.eq(None::<NaiveDateTime>),
+                    last_command_record.eq(None::<NaiveDateTime>),
+                    last_button_record.eq(None::<NaiveDateTime>),
+                ))
+                .execute(conn)
+        )?;
+        self.attempts = 0;
+        self.attempts_window_start = None;
+        self.last_command_record = None;
+        self.last_button_record = None;
+        Ok(())
+    }
+
+    /// Add attempt to user attempts, the first attempt starts the attempts window
+    pub fn make_attempt(&mut self, conn: &mut DbConnection) -> DieselResult<()> {
+        use super::schema::users::dsl::{attempts, attempts_window_start, telegram_id, users};
+        let window_start: NaiveDateTime = self
+            .attempts_window_start
+            .unwrap_or_else(|| offset::Utc::now().naive_utc());
+        db!(
+            conn,
+            update(users.filter(telegram_id.eq(&self.telegram_id)))
+                .set((
+                    attempts.eq(self.attempts + 1),
+                    attempts_window_start.eq(window_start),
+                ))
+                .execute(conn)
+        )?;
         self.attempts += 1;
+        self.attempts_window_start = Some(window_start);
+        Ok(())
+    }
+
+    /// Returns the seconds until the attempts reset, `None` if the attempts will never reset
+    /// (`attempts_window` config is `0`) or the attempts window doesn't start yet
+    pub fn attempts_reset_in(&self, conn: &mut DbConnection) -> Option<i64> {
+        let attempts_window: i64 = Config::integer("attempts_window", conn);
+        match self.attempts_window_start {
+            Some(window_start) if attempts_window > 0 => Some(
+                (window_start + Duration::seconds(attempts_window)
+                    - offset::Utc::now().naive_utc())
+                .num_seconds()
+                .max(0),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Reset the attempts of all users whose attempts window is over,
+    /// returns the number of the reset users
+    pub fn reset_expired_attempts(conn: &mut DbConnection) -> DieselResult<usize> {
+        use super::schema::users::dsl::{attempts, attempts_window_start, users};
+        let attempts_window: i64 = Config::integer("attempts_window", conn);
+        if attempts_window <= 0 {
+            return Ok(0);
+        }
+        db!(
+            conn,
+            update(
+                users.filter(
+                    attempts_window_start
+                        .le(offset::Utc::now().naive_utc() - Duration::seconds(attempts_window))
+                )
+            )
+            .set((
+                attempts.eq(0),
+                attempts_window_start.eq(None::<NaiveDateTime>),
+            ))
+            .execute(conn)
+        )
+    }
+
+    /// Reset the attempts if the attempts window is over
+    pub fn refresh_attempts(&mut self, conn: &mut DbConnection) -> DieselResult<()> {
+        use super::schema::users::dsl::{attempts, attempts_window_start, users};
+        if self.attempts_reset_in(conn) == Some(0) {
+            db!(
+                conn,
+                update(users.find(self.id))
+                    .set((
+                        attempts.eq(0),
+                        attempts_window_start.eq(None::<NaiveDateTime>),
+                    ))
+                    .execute(conn)
+            )?;
+            self.attempts = 0;
+            self.attempts_window_start = None;
+        }
         Ok(())
     }
 
     /// update `last_command_record` (add new record)
-    pub fn make_command_record(&mut self, conn: &mut SqliteConnection) -> DieselResult<()> {
+    pub fn make_command_record(&mut self, conn: &mut DbConnection) -> DieselResult<()> {
         use super::schema::users::dsl::{last_command_record, telegram_id, users};
         let timestamp = NaiveDateTime::from_timestamp(offset::Utc::now().timestamp(), 0);
-        update(users.filter(telegram_id.eq(&self.telegram_id)))
-            .set(last_command_record.eq(timestamp))
-            .execute(conn)?;
+        db!(
+            conn,
+            update(users.filter(telegram_id.eq(&self.telegram_id)))
+                .set(last_command_record.eq(timestamp))
+                .execute(conn)
+        )?;
         self.last_command_record = Some(timestamp);
         Ok(()) // The attempt make it in `share_run_answer`
     }
 
     /// update `last_button_record` (add new record)
-    pub fn make_button_record(&mut self, conn: &mut SqliteConnection) -> DieselResult<()> {
+    pub fn make_button_record(&mut self, conn: &mut DbConnection) -> DieselResult<()> {
         use super::schema::users::dsl::{last_button_record, telegram_id, users};
         let timestamp = NaiveDateTime::from_timestamp(offset::Utc::now().timestamp(), 0);
-        update(users.filter(telegram_id.eq(&self.telegram_id)))
-            .set(last_button_record.eq(timestamp))
-            .execute(conn)?;
+        db!(
+            conn,
+            update(users.filter(telegram_id.eq(&self.telegram_id)))
+                .set(last_button_record.eq(timestamp))
+                .execute(conn)
+        )?;
         self.last_button_record = Some(timestamp);
         Ok(()) // The attempt make it in `share_run_answer`
     }
@@ -326,12 +1435,15 @@ impl Users {
     pub fn update_language(
         &mut self,
         new_language: &str,
-        conn: &mut SqliteConnection,
+        conn: &mut DbConnection,
     ) -> DieselResult<()> {
         use super::schema::users::dsl::{language, users};
-        update(users.find(self.id))
-            .set(language.eq(new_language))
-            .execute(conn)?;
+        db!(
+            conn,
+            update(users.find(self.id))
+                .set(language.eq(new_language))
+                .execute(conn)
+        )?;
         self.language = new_language.into();
         Ok(())
     }
@@ -340,12 +1452,15 @@ impl Users {
     pub fn update_telegram_fullname(
         &mut self,
         new_telegram_fullname: &str,
-        conn: &mut SqliteConnection,
+        conn: &mut DbConnection,
     ) -> DieselResult<()> {
         use super::schema::users::dsl::{telegram_fullname, users};
-        update(users.find(self.id))
-            .set(telegram_fullname.eq(new_telegram_fullname))
-            .execute(conn)?;
+        db!(
+            conn,
+            update(users.find(self.id))
+                .set(telegram_fullname.eq(new_telegram_fullname))
+                .execute(conn)
+        )?;
         self.telegram_fullname = new_telegram_fullname.into();
         Ok(())
     }
@@ -354,23 +1469,22 @@ impl Users {
     pub fn update_username(
         &mut self,
         new_username: &Option<String>,
-        conn: &mut SqliteConnection,
+        conn: &mut DbConnection,
     ) -> DieselResult<()> {
         use super::schema::users::dsl::{username, users};
-        update(users.find(self.id))
-            .set(username.eq(new_username))
-            .execute(conn)?;
+        db!(
+            conn,
+            update(users.find(self.id))
+                .set(username.eq(new_username))
+                .execute(conn)
+        )?;
         self.username = new_username.clone();
         Ok(())
     }
 
     /// Returns `true` if user can send command to bot
-    pub fn can_send_command(&self, conn: &mut SqliteConnection) -> bool {
-        let command_delay: i64 = // default value is 15
-        Config::get_or_add("command_delay", "15", conn)
-            .value
-            .parse::<i64>()
-            .expect("`command_delay` config should be integer");
+    pub fn can_send_command(&self, conn: &mut DbConnection) -> bool {
+        let command_delay: i64 = self.current_command_delay(conn);
         ((self.last_command_record.is_none())
             || ((self.last_command_record.unwrap().timestamp() + command_delay)
                 <= offset::Utc::now().timestamp()))
@@ -378,12 +1492,8 @@ impl Users {
     }
 
     /// Returns `true` if user can click button
-    pub fn can_click_button(&self, conn: &mut SqliteConnection) -> bool {
-        let button_delay: i64 = // default value is 2
-        Config::get_or_add("button_delay", "2", conn)
-            .value
-            .parse::<i64>()
-            .expect("`button_delay` config should be integer");
+    pub fn can_click_button(&self, conn: &mut DbConnection) -> bool {
+        let button_delay: i64 = self.current_button_delay(conn);
         ((self.last_button_record.is_none())
             || ((self.last_button_record.unwrap().timestamp() + button_delay)
                 <= offset::Utc::now().timestamp()))
@@ -393,24 +1503,90 @@ impl Users {
     /// create new source code for user
     pub fn new_source_code(
         &self,
-        conn: &mut SqliteConnection,
+        conn: &mut DbConnection,
         source_code: &Code,
     ) -> DieselResult<SourceCode> {
         NewSourceCode::new(conn, source_code, self)?.save(conn)
     }
 
     /// Returns source codes of user
-    pub fn source_codes(&self, conn: &mut SqliteConnection) -> Option<Vec<SourceCode>> {
+    pub fn source_codes(&self, conn: &mut DbConnection) -> Option<Vec<SourceCode>> {
         use super::schema::source_codes::dsl::{source_codes, user_id};
-        if let Ok(sources) = source_codes
-            .filter(user_id.eq(self.id))
-            .load::<SourceCode>(conn)
-        {
+        if let Ok(sources) = db!(
+            conn,
+            source_codes
+                .filter(user_id.eq(self.id))
+                .load::<SourceCode>(conn)
+        ) {
             Some(sources)
         } else {
             None
         }
     }
+
+    /// Returns number of user snippets
+    pub fn snippets_count(&self, conn: &mut DbConnection) -> DieselResult<i64> {
+        use super::schema::snippets::dsl::{snippets, user_id};
+        db!(
+            conn,
+            snippets
+                .filter(user_id.eq(self.id))
+                .count()
+                .get_result(conn)
+        )
+    }
+
+    /// Returns a page of user snippets with their source codes, sorted by name (the first page is 0)
+    pub fn snippets_page(
+        &self,
+        page: i64,
+        per_page: i64,
+        conn: &mut DbConnection,
+    ) -> DieselResult<Vec<(Snippet, SourceCode)>> {
+        use super::schema::snippets::dsl::{name, snippets, user_id};
+        use super::schema::source_codes::dsl::source_codes;
+        db!(
+            conn,
+            snippets
+                .inner_join(source_codes)
+                .filter(user_id.eq(self.id))
+                .order(name.asc())
+                .limit(per_page)
+                .offset(page * per_page)
+                .load::<(Snippet, SourceCode)>(conn)
+        )
+    }
+
+    /// Returns number of user source codes
+    pub fn source_codes_count(&self, conn: &mut DbConnection) -> DieselResult<i64> {
+        use super::schema::source_codes::dsl::{source_codes, user_id};
+        db!(
+            conn,
+            source_codes
+                .filter(user_id.eq(self.id))
+                .count()
+                .get_result(conn)
+        )
+    }
+
+    /// Returns a page of user source codes, newest first (the first page is 0)
+    pub fn source_codes_page(
+        &self,
+        page: i64,
+        per_page: i64,
+        conn: &mut DbConnection,
+    ) -> DieselResult<Vec<SourceCode>> {
+        use super::schema::source_codes::dsl::{created_at, id, source_codes, user_id};
+        db!(
+            conn,
+            source_codes
+                .filter(user_id.eq(self.id))
+                .order((created_at.desc(), id.desc()))
+                .limit(per_page)
+                .offset(page * per_page)
+                .load::<SourceCode>(conn)
+        )
+    }
 }
 
 impl NewUser {
@@ -424,42 +1600,57 @@ impl NewUser {
             username,
             telegram_id: telegram_id.into(),
             telegram_fullname: telegram_fullname.into(),
+            language: SETTINGS.i18n.default_language.clone(),
         }
     }
 
     /// save object in database
-    pub fn save(&self, conn: &mut SqliteConnection) -> DieselResult<Users> {
-        diesel::insert_into(users::table)
-            .values(self)
-            .execute(conn)?;
+    pub fn save(&self, conn: &mut DbConnection) -> DieselResult<Users> {
+        db!(
+            conn,
+            diesel::insert_into(users::table).values(self).execute(conn)
+        )?;
         Users::try_from((self, conn))
     }
 }
 
 impl NewSourceCode {
     /// Make new object, you can save it in database use save method
-    pub fn new(
-        conn: &mut SqliteConnection,
-        source_code: &Code,
-        author: &Users,
-    ) -> DieselResult<Self> {
+    pub fn new(conn: &mut DbConnection, source_code: &Code, author: &Users) -> DieselResult<Self> {
         Ok(Self {
             source_code: source_code.source_code.to_string(),
             version: source_code.version.to_string(),
             edition: source_code.edition.to_string(),
             mode: source_code.mode.to_string(),
             code: SourceCode::code(conn)?,
+            hash: source_code.hash(),
             user_id: author.id as i32,
             created_at: NaiveDateTime::from_timestamp(offset::Utc::now().timestamp(), 0),
         })
     }
 
-    /// save object in database
-    pub fn save(&self, conn: &mut SqliteConnection) -> DieselResult<SourceCode> {
+    /// save object in database, if the author already have the same source code
+    /// with the same options the saved one will be returned
+    pub fn save(&self, conn: &mut DbConnection) -> DieselResult<SourceCode> {
+        if let Ok(mut source) = SourceCode::get_by_hash(self.user_id, &self.hash, conn) {
+            // the reused source code is renewed, so it's not expired while its new buttons are used
+            use super::schema::source_codes::dsl::created_at;
+            db!(
+                conn,
+                update(source_codes::table.find(source.id))
+                    .set(created_at.eq(self.created_at))
+                    .execute(conn)
+            )?;
+            source.created_at = self.created_at;
+            return Ok(source);
+        }
         if !SourceCode::code_is_exist(conn, &self.code) {
-            diesel::insert_into(source_codes::table)
-                .values(self)
-                .execute(conn)?;
+            db!(
+                conn,
+                diesel::insert_into(source_codes::table)
+                    .values(self)
+                    .execute(conn)
+            )?;
         }
 
         SourceCode::try_from((self, conn))
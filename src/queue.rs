@@ -0,0 +1,338 @@
+// rpg_bot - Telegram bot 🤖, help you to run and share Rust code in Telegram via Rust playground 🦀
+// Source code: <https://github.com/TheAwiteb/rpg_bot>
+//
+// Copyright (C) 2022 TheAwiteb <awiteb@hotmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Execution queue of the Rust playground requests, with global and per user concurrency limits
+//! (`backend.concurrency` and `backend.user_concurrency` settings), the requests start in order,
+//! and the cancellation of the in-flight requests (the cancel button of the wait message)
+
+use crate::{metrics, settings::SETTINGS};
+use lazy_static::lazy_static;
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    sync::Mutex,
+};
+use tokio::sync::{oneshot, Notify};
+
+#[derive(Default)]
+struct QueueState {
+    /// The id of the next ticket
+    next_id: u64,
+    /// The waiting tickets `(ticket id, user id)`, in order
+    waiting: VecDeque<(u64, i32)>,
+    /// Number of the running requests
+    running: usize,
+    /// Number of the running requests, by user id
+    running_by_user: HashMap<i32, usize>,
+}
+
+/// The cancel senders with the user id of the request, by the wait message `(chat id, message id)`
+type Cancels = HashMap<(i64, i32), (i32, oneshot::Sender<()>)>;
+
+lazy_static! {
+    static ref QUEUE: Mutex<QueueState> = Mutex::new(QueueState::default());
+    /// Notified when the queue advances
+    static ref ADVANCED: Notify = Notify::new();
+    /// The cancel senders of the in-flight requests
+    static ref CANCELS: Mutex<Cancels> = Mutex::new(HashMap::new());
+}
+
+/// Place in the queue, leaves the queue when dropped
+pub struct Ticket {
+    id: u64,
+    user_id: i32,
+}
+
+/// Permission to send a Rust playground request, frees its place when dropped
+pub struct Permit {
+    user_id: i32,
+}
+
+impl QueueState {
+    fn user_has_capacity(&self, user_id: i32) -> bool {
+        self.running_by_user.get(&user_id).copied().unwrap_or(0) < SETTINGS.backend.user_concurrency
+    }
+
+    /// Returns the position of the ticket (starts from 1), `None` if it's not waiting
+    fn position(&self, id: u64) -> Option<usize> {
+        self.waiting
+            .iter()
+            .position(|(ticket_id, _)| *ticket_id == id)
+            .map(|index| index + 1)
+    }
+
+    /// Returns `true` if the ticket can start, the first waiting ticket that its user has
+    /// capacity starts first
+    fn can_start(&self, id: u64) -> bool {
+        self.running < SETTINGS.backend.concurrency
+            && self
+                .waiting
+                .iter()
+                .find(|(_, user_id)| self.user_has_capacity(*user_id))
+                .is_some_and(|(ticket_id, _)| *ticket_id == id)
+    }
+
+    /// Join the queue, returns the ticket id
+    fn join(&mut self, user_id: i32) -> u64 {
+        let id: u64 = self.next_id;
+        self.next_id += 1;
+        self.waiting.push_back((id, user_id));
+        id
+    }
+
+    /// Start the ticket if it can start, returns `true` if it's started
+    fn try_start(&mut self, id: u64, user_id: i32) -> bool {
+        if !self.can_start(id) {
+            return false;
+        }
+        self.waiting.retain(|(ticket_id, _)| *ticket_id != id);
+        self.running += 1;
+        *self.running_by_user.entry(user_id).or_insert(0) += 1;
+        true
+    }
+
+    /// Leave the queue without starting, returns `false` if the ticket is not waiting
+    fn leave(&mut self, id: u64) -> bool {
+        let waiting: usize = self.waiting.len();
+        self.waiting.retain(|(ticket_id, _)| *ticket_id != id);
+        self.waiting.len() != waiting
+    }
+
+    /// Free the place of the finished (or cancelled) request of the user
+    fn finish(&mut self, user_id: i32) {
+        self.running -= 1;
+        if let Some(running) = self.running_by_user.get_mut(&user_id) {
+            *running -= 1;
+            if *running == 0 {
+                self.running_by_user.remove(&user_id);
+            }
+        }
+    }
+
+    fn update_metrics(&self) {
+        metrics::QUEUE_WAITING.set(self.waiting.len() as i64);
+        metrics::QUEUE_RUNNING.set(self.running as i64);
+    }
+}
+
+impl Ticket {
+    /// Join the queue
+    pub fn new(user_id: i32) -> Self {
+        let mut queue = QUEUE.lock().unwrap();
+        let id: u64 = queue.join(user_id);
+        queue.update_metrics();
+        Self { id, user_id }
+    }
+
+    /// Wait for the turn of the ticket, `on_position` is called with the position in the queue
+    /// when the request can't start yet, and again every time the position changes
+    pub async fn wait<F, Fut>(self, mut on_position: F) -> Permit
+    where
+        F: FnMut(usize) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let mut last_position: Option<usize> = None;
+        loop {
+            let (notified, position) = {
+                let mut queue = QUEUE.lock().unwrap();
+                if queue.try_start(self.id, self.user_id) {
+                    queue.update_metrics();
+                    drop(queue);
+                    // the positions of the other tickets are changed
+                    ADVANCED.notify_waiters();
+                    return Permit {
+                        user_id: self.user_id,
+                    };
+                }
+                // created before unlock the queue, so the advances after that are not missed
+                (ADVANCED.notified(), queue.position(self.id))
+            };
+            if let Some(position) = position.filter(|position| Some(*position) != last_position) {
+                last_position = Some(position);
+                on_position(position).await;
+            }
+            notified.await;
+        }
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        let mut queue = QUEUE.lock().unwrap();
+        if queue.leave(self.id) {
+            // left the queue without starting
+            queue.update_metrics();
+            drop(queue);
+            ADVANCED.notify_waiters();
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut queue = QUEUE.lock().unwrap();
+        queue.finish(self.user_id);
+        queue.update_metrics();
+        drop(queue);
+        ADVANCED.notify_waiters();
+    }
+}
+
+/// Cancellation of the in-flight request of the wait message, unregistered when dropped
+pub struct Cancellation {
+    key: (i64, i32),
+    receiver: oneshot::Receiver<()>,
+}
+
+impl Cancellation {
+    /// Register the request of the wait message, so its user can cancel it
+    pub fn new(chat_id: i64, message_id: i32, user_id: i32) -> Self {
+        let (sender, receiver) = oneshot::channel();
+        CANCELS
+            .lock()
+            .unwrap()
+            .insert((chat_id, message_id), (user_id, sender));
+        Self {
+            key: (chat_id, message_id),
+            receiver,
+        }
+    }
+
+    /// Wait until the request is cancelled
+    pub async fn cancelled(&mut self) {
+        if (&mut self.receiver).await.is_err() {
+            // the sender is dropped without cancel, never cancelled
+            futures::future::pending::<()>().await;
+        }
+    }
+
+    /// Cancel the request of the wait message, returns `false` if there is no in-flight request
+    /// for the message or it's not for the user
+    pub fn cancel(chat_id: i64, message_id: i32, user_id: i32) -> bool {
+        let mut cancels = CANCELS.lock().unwrap();
+        match cancels.get(&(chat_id, message_id)) {
+            Some((request_user_id, _)) if *request_user_id == user_id => cancels
+                .remove(&(chat_id, message_id))
+                .is_some_and(|(_, sender)| sender.send(()).is_ok()),
+            _ => false,
+        }
+    }
+}
+
+impl Drop for Cancellation {
+    fn drop(&mut self) {
+        CANCELS.lock().unwrap().remove(&self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn global_limit() {
+        let mut queue = QueueState::default();
+        // every ticket for a different user, so the user limit doesn't matter
+        let users: Vec<i32> = (0..SETTINGS.backend.concurrency as i32).collect();
+        for user_id in &users {
+            let id: u64 = queue.join(*user_id);
+            assert!(queue.try_start(id, *user_id));
+        }
+        let user_id: i32 = users.len() as i32;
+        let id: u64 = queue.join(user_id);
+        assert!(!queue.try_start(id, user_id));
+        assert_eq!(queue.position(id), Some(1));
+
+        queue.finish(users[0]);
+        assert!(queue.try_start(id, user_id));
+        assert_eq!(queue.running, SETTINGS.backend.concurrency);
+    }
+
+    #[test]
+    fn user_limit() {
+        let mut queue = QueueState::default();
+        let mut running: Vec<u64> = Vec::new();
+        for _ in 0..SETTINGS.backend.user_concurrency {
+            let id: u64 = queue.join(1);
+            assert!(queue.try_start(id, 1));
+            running.push(id);
+        }
+        let waiting: u64 = queue.join(1);
+        assert!(!queue.try_start(waiting, 1));
+
+        if SETTINGS.backend.user_concurrency < SETTINGS.backend.concurrency {
+            // the other users are not blocked by the waiting ticket of the user
+            let other: u64 = queue.join(2);
+            assert!(queue.try_start(other, 2));
+            queue.finish(2);
+        }
+
+        queue.finish(1);
+        assert!(queue.try_start(waiting, 1));
+        assert_eq!(queue.running_by_user.get(&1).copied(), Some(running.len()));
+    }
+
+    #[test]
+    fn fifo_order() {
+        let mut queue = QueueState::default();
+        let tickets: Vec<(u64, i32)> = (1..=3)
+            .map(|user_id| (queue.join(user_id), user_id))
+            .collect();
+        assert_eq!(queue.position(tickets[2].0), Some(3));
+        // the later tickets can't start before the first one
+        assert!(!queue.try_start(tickets[2].0, tickets[2].1));
+        assert!(!queue.try_start(tickets[1].0, tickets[1].1));
+        for (index, (id, user_id)) in tickets.iter().enumerate() {
+            if index < SETTINGS.backend.concurrency {
+                assert!(queue.try_start(*id, *user_id));
+            }
+        }
+    }
+
+    #[test]
+    fn cancel_releases_slot() {
+        let mut queue = QueueState::default();
+        let first: u64 = queue.join(1);
+        let second: u64 = queue.join(2);
+        // the cancelled waiting ticket leaves the queue, the next one is the first
+        assert!(queue.leave(first));
+        assert!(!queue.leave(first));
+        assert_eq!(queue.position(second), Some(1));
+        assert!(queue.try_start(second, 2));
+
+        // the cancelled running request frees its place
+        queue.finish(2);
+        assert_eq!(queue.running, 0);
+        assert!(queue.running_by_user.is_empty());
+    }
+
+    #[test]
+    fn cancellation() {
+        let (chat_id, message_id): (i64, i32) = (-1, 1);
+        let mut cancellation = Cancellation::new(chat_id, message_id, 1);
+        // only the user of the request can cancel it
+        assert!(!Cancellation::cancel(chat_id, message_id, 2));
+        assert!(Cancellation::cancel(chat_id, message_id, 1));
+        futures::executor::block_on(cancellation.cancelled());
+        assert!(!Cancellation::cancel(chat_id, message_id, 1));
+
+        drop(Cancellation::new(chat_id, message_id + 1, 1));
+        // the finished request is unregistered
+        assert!(!Cancellation::cancel(chat_id, message_id + 1, 1));
+    }
+}
@@ -16,13 +16,45 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
-use crate::models::NewSourceCode;
-use reqwest::Client;
-use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, error::Error};
+use crate::{
+    errors::{RpgError, RpgResult},
+    metrics,
+    models::NewSourceCode,
+    settings::SETTINGS,
+};
+use lazy_static::lazy_static;
+use reqwest::{Client, RequestBuilder};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
-const RUN_URL: &str = "https://play.rust-lang.org/execute";
-const GIST_GEN_URL: &str = "https://play.rust-lang.org/meta/gist/";
+/// Rust playground endpoints, relative to the `playground_url` setting
+const RUN_PATH: &str = "/execute";
+const GIST_GEN_PATH: &str = "/meta/gist/";
+
+lazy_static! {
+    /// The Rust playground client, with the timeouts of the settings
+    static ref CLIENT: Client = Client::builder()
+        .timeout(Duration::from_secs(SETTINGS.backend.timeout))
+        .connect_timeout(Duration::from_secs(SETTINGS.backend.connect_timeout))
+        .build()
+        .expect("Cannot build the Rust playground client");
+    static ref BREAKER: Mutex<CircuitBreaker> = Mutex::new(CircuitBreaker::default());
+}
+
+/// Circuit breaker of the Rust playground, opens after `breaker_threshold` consecutive failures
+/// so the requests fail immediately, after `breaker_cooldown` one request is sent to try it
+#[derive(Default)]
+struct CircuitBreaker {
+    /// Consecutive failures
+    failures: u32,
+    /// The breaker is open until this time
+    open_until: Option<Instant>,
+}
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -115,27 +147,39 @@ impl Code {
         }
     }
 
-    pub fn is_valid(&self) -> Result<bool, String> {
+    /// Returns the content hash of the code (source code and options),
+    /// the same code with the same options always have the same hash
+    pub fn hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        for part in [&self.source_code, &self.version, &self.mode, &self.edition] {
+            hasher.update(part.as_bytes());
+            // separator, to not mix the parts
+            hasher.update([0]);
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    pub fn is_valid(&self) -> RpgResult<()> {
         if !["stable", "beta", "nightly"].contains(&self.version.to_ascii_lowercase().as_str()) {
-            return Err(format!(
+            return Err(RpgError::Validation(format!(
                 "Invalid version ✖️: '{}', valid is 'stable', 'beta', 'nightly'",
                 self.version
-            ));
+            )));
         }
         if !["debug", "release"].contains(&self.mode.to_ascii_lowercase().as_str()) {
-            return Err(format!(
+            return Err(RpgError::Validation(format!(
                 "Invalid mode ✖️: '{}', valid is 'debug', 'release'",
                 self.mode
-            ));
+            )));
         }
         if !["2015", "2018", "2021"].contains(&self.edition.as_str()) {
-            return Err(format!(
+            return Err(RpgError::Validation(format!(
                 "Invalid edition ✖️: '{}', valid is '2015', '2018', '2021'",
                 self.edition
-            ));
+            )));
         }
 
-        Ok(true)
+        Ok(())
     }
 }
 
@@ -150,64 +194,158 @@ impl Default for Code {
     }
 }
 
-async fn get_run_response(code: &Code) -> Result<RunRes, Box<dyn Error + Send + Sync>> {
+impl CircuitBreaker {
+    fn cooldown() -> Duration {
+        Duration::from_secs(SETTINGS.backend.breaker_cooldown)
+    }
+
+    /// Returns `true` if the request can be sent, when the cooldown is over one request is
+    /// allowed and the others wait for another cooldown
+    fn allow(&mut self) -> bool {
+        match self.open_until {
+            Some(until) if Instant::now() < until => false,
+            Some(_) => {
+                self.open_until = Some(Instant::now() + Self::cooldown());
+                true
+            }
+            None => true,
+        }
+    }
+
+    fn success(&mut self) {
+        if self.open_until.take().is_some() {
+            log::info!("Rust playground is back, the circuit breaker is closed");
+            metrics::PLAYGROUND_CIRCUIT_OPEN.set(0);
+        }
+        self.failures = 0;
+    }
+
+    fn failure(&mut self) {
+        self.failures += 1;
+        let threshold: u32 = SETTINGS.backend.breaker_threshold;
+        if threshold > 0 && self.failures >= threshold {
+            if self.open_until.is_none() {
+                log::warn!(
+                    "Rust playground failed {} times, the circuit breaker is open",
+                    self.failures
+                );
+                metrics::PLAYGROUND_CIRCUIT_OPEN.set(1);
+            }
+            self.open_until = Some(Instant::now() + Self::cooldown());
+        }
+    }
+}
+
+/// Send the request and returns its json response, the failed requests are retried with backoff
+/// if they are idempotent (or if the request is not sent, connect error)
+async fn send<T: DeserializeOwned>(request: RequestBuilder, idempotent: bool) -> RpgResult<T> {
+    if !BREAKER.lock().unwrap().allow() {
+        return Err(RpgError::PlaygroundUnavailable);
+    }
+    let mut retries: u32 = 0;
+    loop {
+        let result: reqwest::Result<T> = async {
+            request
+                .try_clone()
+                .expect("The Rust playground requests have no stream body")
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await
+        }
+        .await;
+        match result {
+            Ok(response) => {
+                BREAKER.lock().unwrap().success();
+                return Ok(response);
+            }
+            Err(err) => {
+                // the timeouts and the invalid responses have no status, they are retried if
+                // the request is idempotent
+                let is_server_error: bool =
+                    err.status().is_none_or(|status| status.is_server_error());
+                if retries < SETTINGS.backend.retries
+                    && (err.is_connect() || (idempotent && is_server_error))
+                {
+                    // saturated instead of overflow with the big backoff
+                    tokio::time::sleep(Duration::from_millis(
+                        SETTINGS
+                            .backend
+                            .retry_backoff
+                            .saturating_mul(2_u64.checked_pow(retries).unwrap_or(u64::MAX)),
+                    ))
+                    .await;
+                    retries += 1;
+                    log::debug!("Retry the Rust playground request ({}): {}", retries, err);
+                    continue;
+                }
+                // only the connection errors and 5xx responses mean the playground is down,
+                // not the client errors (4xx), the timeouts (slow code) or the invalid responses
+                if err.is_connect() || err.status().is_some_and(|status| status.is_server_error()) {
+                    BREAKER.lock().unwrap().failure();
+                }
+                return Err(err.into());
+            }
+        }
+    }
+}
+
+async fn get_run_response(code: &Code) -> RpgResult<RunRes> {
     let body = RunReq::from(code);
-    Ok(Client::new()
-        .post(RUN_URL)
-        .json(&body)
-        .send()
-        .await?
-        .json()
-        .await?)
+    // running the code has no side effects, it can be retried
+    send(
+        CLIENT
+            .post(SETTINGS.backend.playground_url.clone() + RUN_PATH)
+            .json(&body),
+        true,
+    )
+    .await
 }
 
-async fn get_share_response(code: &Code) -> Result<GistRes, Box<dyn Error + Send + Sync>> {
+async fn get_share_response(code: &Code) -> RpgResult<GistRes> {
     let mut req_json = HashMap::new();
     req_json.insert("code", &code.source_code);
-    Ok(Client::new()
-        .post(GIST_GEN_URL)
-        .json(&req_json)
-        .send()
-        .await
-        .unwrap()
-        .json()
-        .await
-        .unwrap())
+    // every request creates a new gist, it's not retried if it was sent
+    send(
+        CLIENT
+            .post(SETTINGS.backend.playground_url.clone() + GIST_GEN_PATH)
+            .json(&req_json),
+        false,
+    )
+    .await
 }
 
-/// Returns Rust playground url for the given code
-pub async fn share(code: &Code) -> Result<String, String> {
-    let res_run: RunRes = get_run_response(code)
-        .await
-        .map_err(|err| format!("{}", err))?;
+/// Returns Rust playground url for the given code,
+/// the compile errors are returned as [`RpgError::Validation`]
+pub async fn share(code: &Code) -> RpgResult<String> {
+    let res_run: RunRes = get_run_response(code).await?;
 
     if res_run.is_valid() {
-        let res_share: GistRes = get_share_response(code)
-            .await
-            .map_err(|err| format!("{}", err))?;
+        let res_share: GistRes = get_share_response(code).await?;
         let url = format!(
-            "https://play.rust-lang.org/?version={}&mode={}&edition={}&gist={}",
-            code.version, code.mode, code.edition, res_share.id
+            "{}/?version={}&mode={}&edition={}&gist={}",
+            SETTINGS.backend.playground_url, code.version, code.mode, code.edition, res_share.id
         );
 
         Ok(url)
     } else {
-        Err(res_run
-            .stderr
-            .replace(
-                "could not compile `playground`",
-                "Source code cannot be shared",
-            )
-            .replace("/playground", "playground"))
+        Err(RpgError::Validation(
+            res_run
+                .stderr
+                .replace(
+                    "could not compile `playground`",
+                    "Source code cannot be shared",
+                )
+                .replace("/playground", "playground"),
+        ))
     }
 }
 
-/// Run the given code in Rust playground and return the output
-pub async fn run(code: &Code) -> Result<String, String> {
-    let res: RunRes = get_run_response(code)
-        .await
-        // Returns error as string for send it to user
-        .map_err(|err| format!("{}", err))?;
+/// Run the given code in Rust playground and return the output,
+/// the output of the invalid code is returned as [`RpgError::Validation`]
+pub async fn run(code: &Code) -> RpgResult<String> {
+    let res: RunRes = get_run_response(code).await?;
 
     let output: String = format!(
         "{}\n{}",
@@ -218,6 +356,76 @@ pub async fn run(code: &Code) -> Result<String, String> {
     if res.is_valid() {
         Ok(output)
     } else {
-        Err(output)
+        Err(RpgError::Validation(output))
+    }
+}
+
+/// Returns `Ok` if the Rust playground is reachable
+pub async fn is_reachable() -> RpgResult<()> {
+    CLIENT
+        .head(&SETTINGS.backend.playground_url)
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns open circuit breaker, after `breaker_threshold` failures
+    fn open_breaker() -> CircuitBreaker {
+        let mut breaker = CircuitBreaker::default();
+        for _ in 0..SETTINGS.backend.breaker_threshold {
+            breaker.failure();
+        }
+        breaker
+    }
+
+    #[test]
+    fn closed_until_threshold() {
+        let mut breaker = CircuitBreaker::default();
+        for _ in 1..SETTINGS.backend.breaker_threshold {
+            breaker.failure();
+            assert!(breaker.allow());
+        }
+        breaker.failure();
+        assert!(!breaker.allow());
+    }
+
+    #[test]
+    fn success_resets_failures() {
+        let mut breaker = CircuitBreaker::default();
+        for _ in 1..SETTINGS.backend.breaker_threshold {
+            breaker.failure();
+        }
+        breaker.success();
+        assert_eq!(breaker.failures, 0);
+        breaker.failure();
+        assert!(breaker.allow());
+    }
+
+    #[test]
+    fn half_open_after_cooldown() {
+        let mut breaker = open_breaker();
+        breaker.open_until = Some(Instant::now() - Duration::from_secs(1));
+        // one request tries the playground, the others wait for another cooldown
+        assert!(breaker.allow());
+        assert!(!breaker.allow());
+
+        breaker.success();
+        assert!(breaker.open_until.is_none());
+        assert!(breaker.allow());
+    }
+
+    #[test]
+    fn half_open_failure_opens_again() {
+        let mut breaker = open_breaker();
+        breaker.open_until = Some(Instant::now() - Duration::from_secs(1));
+        assert!(breaker.allow());
+        breaker.failure();
+        assert!(!breaker.allow());
     }
 }
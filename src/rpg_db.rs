@@ -16,37 +16,186 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
-use crate::models::{DieselResult, NewUser, Users};
-use diesel::prelude::*;
+use crate::{
+    errors::RpgResult,
+    models::{DieselError, DieselResult, NewUser, Users},
+    settings::SETTINGS,
+};
+use diesel::{
+    connection::SimpleConnection,
+    pg::PgConnection,
+    prelude::*,
+    r2d2::{self, CustomizeConnection, ManageConnection, Pool, PooledConnection},
+};
 use json_gettext::{static_json_gettext_build, JSONGetText};
-use std::env;
+use std::time::Duration;
 use teloxide::types::User as TelegramUser;
 
-/// Returns db connection
-pub fn establish_connection() -> SqliteConnection {
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    SqliteConnection::establish(&database_url)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
+pub type DbPool = Pool<DbConnectionManager>;
+pub type PooledDbConnection = PooledConnection<DbConnectionManager>;
+
+/// Database connection, the backend is chosen by `DATABASE_URL` scheme,
+/// `postgres://` and `postgresql://` are PostgreSQL and anything else is SQLite database path
+///
+/// Use [`db!`](crate::db) to run a diesel query on it
+pub enum DbConnection {
+    Sqlite(SqliteConnection),
+    Postgres(PgConnection),
+}
+
+/// Run the expression with the backend connection of [`DbConnection`],
+/// the connection variable is shadowed by the backend connection inside the expression
+///
+/// ```ignore
+/// let user: Users = db!(conn, users.find(id).first(conn))?;
+/// ```
+#[macro_export]
+macro_rules! db {
+    ($conn:ident, $body:expr) => {
+        match &mut *$conn {
+            $crate::rpg_db::DbConnection::Sqlite($conn) => $body,
+            $crate::rpg_db::DbConnection::Postgres($conn) => $body,
+        }
+    };
+}
+
+impl DbConnection {
+    /// Returns `true` if the database url is PostgreSQL url
+    pub fn is_postgres_url(database_url: &str) -> bool {
+        database_url.starts_with("postgres://") || database_url.starts_with("postgresql://")
+    }
+
+    /// Connect to the database of the url
+    pub fn establish(database_url: &str) -> ConnectionResult<Self> {
+        if Self::is_postgres_url(database_url) {
+            PgConnection::establish(database_url).map(Self::Postgres)
+        } else {
+            SqliteConnection::establish(database_url).map(Self::Sqlite)
+        }
+    }
+}
+
+impl SimpleConnection for DbConnection {
+    fn batch_execute(&self, query: &str) -> QueryResult<()> {
+        match self {
+            Self::Sqlite(conn) => conn.batch_execute(query),
+            Self::Postgres(conn) => conn.batch_execute(query),
+        }
+    }
+}
+
+/// Connections manager of the pool, like diesel `ConnectionManager` but for [`DbConnection`]
+#[derive(Debug)]
+pub struct DbConnectionManager {
+    database_url: String,
+}
+
+impl ManageConnection for DbConnectionManager {
+    type Connection = DbConnection;
+    type Error = r2d2::Error;
+
+    fn connect(&self) -> Result<DbConnection, r2d2::Error> {
+        DbConnection::establish(&self.database_url).map_err(r2d2::Error::ConnectionError)
+    }
+
+    fn is_valid(&self, conn: &mut DbConnection) -> Result<(), r2d2::Error> {
+        conn.batch_execute("SELECT 1")
+            .map_err(r2d2::Error::QueryError)
+    }
+
+    fn has_broken(&self, _conn: &mut DbConnection) -> bool {
+        false
+    }
+}
+
+/// Configure the new connections of the pool
+#[derive(Debug)]
+struct ConnectionCustomizer;
+
+impl CustomizeConnection<DbConnection, r2d2::Error> for ConnectionCustomizer {
+    fn on_acquire(&self, conn: &mut DbConnection) -> Result<(), r2d2::Error> {
+        match conn {
+            // WAL journal mode, the readers don't block the writer and the writer doesn't block the readers
+            DbConnection::Sqlite(conn) => conn
+                .batch_execute(&format!(
+                    "PRAGMA journal_mode = WAL; PRAGMA busy_timeout = {};",
+                    SETTINGS.database.busy_timeout
+                ))
+                .map_err(r2d2::Error::QueryError),
+            DbConnection::Postgres(_) => Ok(()),
+        }
+    }
+}
+
+/// Returns db connections pool, shared between the updates handlers
+pub fn establish_pool() -> DbPool {
+    let database_url: &str = SETTINGS
+        .database
+        .url
+        .as_deref()
+        .expect("DATABASE_URL must be set");
+    Pool::builder()
+        .max_size(SETTINGS.database.pool_size)
+        .connection_timeout(Duration::from_secs(SETTINGS.database.connection_timeout))
+        .connection_customizer(Box::new(ConnectionCustomizer))
+        .build(DbConnectionManager {
+            database_url: database_url.to_string(),
+        })
+        .unwrap_or_else(|err| panic!("Error connecting to {}: {}", database_url, err))
 }
 
 /// Returns ctx of languages
 pub fn languages_ctx() -> JSONGetText<'static> {
     static_json_gettext_build!(
-        "English 🇺🇸";
+        SETTINGS.i18n.default_language.as_str();
         "العربية 🇸🇦" => "./i18n/ar_SA.json",
         "English 🇺🇸" => "./i18n/en_US.json",
         "русский 🇷🇺" => "./i18n/ru_RU.json",
     )
-    .unwrap()
+    .expect("The default language should be one of the supported languages")
 }
 
-/// Returns old/new user from telegram user object
-pub fn get_user(conn: &mut SqliteConnection, author: &TelegramUser) -> DieselResult<Users> {
-    Ok(
-        Users::try_from((&NewUser::from(author), conn)).unwrap_or_else(|_| {
-            NewUser::from(author)
-                .save(&mut establish_connection())
-                .unwrap()
-        }),
-    )
+/// Returns old/new user from telegram user object, users in the admins setting (`ADMINS`) will be admins
+pub fn get_user(conn: &mut DbConnection, author: &TelegramUser) -> DieselResult<Users> {
+    let mut user: Users = match Users::try_from((&NewUser::from(author), &mut *conn)) {
+        Ok(user) => user,
+        Err(DieselError::NotFound) => NewUser::from(author).save(conn)?,
+        Err(err) => return Err(err),
+    };
+    if !user.is_admin && SETTINGS.telegram.admins.contains(&user.telegram_id) {
+        user.set_admin(true, conn)?;
+    }
+    user.refresh_attempts(conn)?;
+    user.refresh_ban(conn)?;
+    if !user.is_active {
+        // the user is back (was blocked the bot)
+        user.set_active(true, conn)?;
+    }
+    Ok(user)
+}
+
+/// Returns db connection from the pool, the connection is taken in a blocking task so waiting
+/// for a free connection doesn't block the updates handlers
+pub async fn connection(pool: &DbPool) -> RpgResult<PooledDbConnection> {
+    let pool: DbPool = pool.clone();
+    tokio::task::spawn_blocking(move || Ok(pool.get()?))
+        .await
+        .expect("The database task should not panic")
+}
+
+/// Returns db connection from the pool and the old/new user from telegram user object,
+/// the connection is taken in a blocking task so waiting for a free connection doesn't block the updates handlers
+pub async fn connection_and_user(
+    pool: &DbPool,
+    author: &TelegramUser,
+) -> RpgResult<(PooledDbConnection, Users)> {
+    let pool: DbPool = pool.clone();
+    let author: TelegramUser = author.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut conn: PooledDbConnection = pool.get()?;
+        let user: Users = get_user(&mut conn, &author)?;
+        Ok((conn, user))
+    })
+    .await
+    .expect("The database task should not panic")
 }
@@ -0,0 +1,180 @@
+// rpg_bot - Telegram bot 🤖, help you to run and share Rust code in Telegram via Rust playground 🦀
+// Source code: <https://github.com/TheAwiteb/rpg_bot>
+//
+// Copyright (C) 2022 TheAwiteb <awiteb@hotmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Background jobs (expiry and maintenance), every job runs every its interval config seconds
+
+use crate::{
+    errors::{RpgError, RpgResult},
+    models::{CachedResult, Config, Event, SourceCode, Users},
+    rpg_db::{DbConnection, DbPool},
+    stats,
+};
+use chrono::{offset, Duration as ChronoDuration, NaiveDateTime};
+use diesel::connection::SimpleConnection;
+use lazy_static::lazy_static;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// How often the scheduler checks if there are jobs to run
+const TICK: Duration = Duration::from_secs(30);
+
+/// Background job
+pub struct Job {
+    pub name: &'static str,
+    /// The config of the job interval in seconds, `0` means the job is disabled
+    pub interval_config: &'static str,
+    /// Returns the number of the affected rows
+    task: fn(&mut DbConnection) -> RpgResult<usize>,
+}
+
+/// The background jobs
+pub const JOBS: [Job; 5] = [
+    Job {
+        name: "expire",
+        interval_config: "expire_interval",
+        task: expire,
+    },
+    Job {
+        name: "events_retention",
+        interval_config: "events_retention_interval",
+        task: events_retention,
+    },
+    Job {
+        name: "reset_attempts",
+        interval_config: "reset_attempts_interval",
+        task: reset_attempts,
+    },
+    Job {
+        name: "stats_rollup",
+        interval_config: "stats_rollup_interval",
+        task: stats::rollup,
+    },
+    Job {
+        name: "vacuum",
+        interval_config: "vacuum_interval",
+        task: vacuum,
+    },
+];
+
+/// The last run of a job
+#[derive(Clone)]
+pub struct JobRun {
+    pub time: NaiveDateTime,
+    pub duration: Duration,
+    /// The number of the affected rows, or the error message
+    pub result: Result<usize, String>,
+}
+
+lazy_static! {
+    /// The last runs of the jobs, by job name
+    static ref LAST_RUNS: Mutex<HashMap<&'static str, JobRun>> = Mutex::new(HashMap::new());
+}
+
+/// Remove the expired source codes and cached results
+fn expire(conn: &mut DbConnection) -> RpgResult<usize> {
+    Ok(SourceCode::filter_source_codes(conn)? + CachedResult::filter_results(conn)?)
+}
+
+/// Remove the events that are older than the retention
+fn events_retention(conn: &mut DbConnection) -> RpgResult<usize> {
+    Ok(Event::remove_old(conn)?)
+}
+
+/// Reset the attempts of the users whose attempts window is over
+fn reset_attempts(conn: &mut DbConnection) -> RpgResult<usize> {
+    Ok(Users::reset_expired_attempts(conn)?)
+}
+
+/// Rebuild the database file (SQLite) or reclaim the dead rows storage (PostgreSQL)
+fn vacuum(conn: &mut DbConnection) -> RpgResult<usize> {
+    let query: &str = match conn {
+        DbConnection::Sqlite(_) => "VACUUM",
+        DbConnection::Postgres(_) => "VACUUM ANALYZE",
+    };
+    conn.batch_execute(query)?;
+    Ok(0)
+}
+
+impl Job {
+    /// Returns the last run of the job, `None` if it didn't run yet
+    pub fn last_run(&self) -> Option<JobRun> {
+        LAST_RUNS.lock().unwrap().get(self.name).cloned()
+    }
+
+    /// Returns `true` if the job is enabled and its interval is over
+    fn is_due(&self, conn: &mut DbConnection) -> bool {
+        let interval: i64 = Config::integer(self.interval_config, conn);
+        interval > 0
+            && self.last_run().is_none_or(|run| {
+                offset::Utc::now().naive_utc() - run.time >= ChronoDuration::seconds(interval)
+            })
+    }
+
+    /// Run the job, log and save its result
+    fn run(&self, conn: &mut DbConnection) {
+        let time: NaiveDateTime = offset::Utc::now().naive_utc();
+        let started: Instant = Instant::now();
+        let result: RpgResult<usize> = (self.task)(conn);
+        let duration: Duration = started.elapsed();
+        match &result {
+            Ok(affected) => log::info!(
+                "The `{}` job is done in {:?}, {} affected rows",
+                self.name,
+                duration,
+                affected
+            ),
+            Err(err) => {
+                log::error!("The `{}` job is failed", self.name);
+                err.log();
+            }
+        }
+        LAST_RUNS.lock().unwrap().insert(
+            self.name,
+            JobRun {
+                time,
+                duration,
+                result: result.map_err(|err| err.to_string()),
+            },
+        );
+    }
+}
+
+/// Run the jobs when their intervals are over, forever
+pub async fn run(pool: DbPool) {
+    let mut ticks = tokio::time::interval(TICK);
+    loop {
+        ticks.tick().await;
+        let pool: DbPool = pool.clone();
+        // the jobs are blocking (database queries), they shouldn't block the updates handlers
+        tokio::task::spawn_blocking(move || match pool.get() {
+            Ok(mut conn) => {
+                for job in JOBS.iter() {
+                    if job.is_due(&mut conn) {
+                        job.run(&mut conn);
+                    }
+                }
+            }
+            Err(err) => RpgError::from(err).log(),
+        })
+        .await
+        .ok();
+    }
+}
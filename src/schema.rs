@@ -16,6 +16,16 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
+table! {
+    cached_results (id) {
+        id -> Integer,
+        hash -> Text,
+        kind -> Text,
+        output -> Text,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     config (id) {
         id -> Integer,
@@ -24,6 +34,47 @@ table! {
     }
 }
 
+table! {
+    events (id) {
+        id -> Integer,
+        user_id -> Integer,
+        chat_id -> BigInt,
+        kind -> Text,
+        command -> Text,
+        version -> Nullable<Text>,
+        mode -> Nullable<Text>,
+        edition -> Nullable<Text>,
+        duration -> Nullable<Integer>,
+        success -> Bool,
+        output_size -> Integer,
+        is_cached -> Bool,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    moderation_logs (id) {
+        id -> Integer,
+        admin_id -> Integer,
+        user_id -> Integer,
+        action -> Text,
+        reason -> Nullable<Text>,
+        until -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    snippets (id) {
+        id -> Integer,
+        user_id -> Integer,
+        source_code_id -> Integer,
+        name -> Text,
+        tags -> Text,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     source_codes (id) {
         id -> Integer,
@@ -34,6 +85,8 @@ table! {
         edition -> Text,
         mode -> Text,
         created_at -> Timestamp,
+        is_public -> Bool,
+        hash -> Nullable<Text>,
     }
 }
 
@@ -48,9 +101,29 @@ table! {
         attempts_maximum -> Integer,
         last_command_record -> Nullable<Timestamp>,
         last_button_record -> Nullable<Timestamp>,
+        is_admin -> Bool,
+        is_banned -> Bool,
+        is_active -> Bool,
+        command_delay -> Nullable<Integer>,
+        button_delay -> Nullable<Integer>,
+        attempts_window_start -> Nullable<Timestamp>,
+        is_shadow_banned -> Bool,
+        ban_reason -> Nullable<Text>,
+        banned_until -> Nullable<Timestamp>,
     }
 }
 
+joinable!(events -> users (user_id));
+joinable!(snippets -> source_codes (source_code_id));
+joinable!(snippets -> users (user_id));
 joinable!(source_codes -> users (user_id));
 
-allow_tables_to_appear_in_same_query!(config, source_codes, users,);
+allow_tables_to_appear_in_same_query!(
+    cached_results,
+    config,
+    events,
+    moderation_logs,
+    snippets,
+    source_codes,
+    users,
+);
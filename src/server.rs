@@ -0,0 +1,56 @@
+// rpg_bot - Telegram bot 🤖, help you to run and share Rust code in Telegram via Rust playground 🦀
+// Source code: <https://github.com/TheAwiteb/rpg_bot>
+//
+// Copyright (C) 2022 TheAwiteb <awiteb@hotmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{health, metrics, rpg_db::DbPool, settings::SETTINGS};
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use std::{convert::Infallible, net::SocketAddr};
+
+/// HTTP server handler
+///
+/// - `GET /metrics` prometheus metrics, if the metrics are enabled (`METRICS_ENABLED`)
+/// - `GET /health` liveness
+/// - `GET /ready` readiness (database, migrations and Rust playground)
+async fn handler(request: Request<Body>, pool: DbPool) -> Result<Response<Body>, Infallible> {
+    Ok(match (request.method(), request.uri().path()) {
+        (&Method::GET, "/metrics") if SETTINGS.server.metrics => metrics::response(),
+        (&Method::GET, "/health") => health::liveness(),
+        (&Method::GET, "/ready") => health::readiness(pool).await,
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap(),
+    })
+}
+
+/// Serve the HTTP server on the address, the readiness check uses the database pool
+pub async fn serve(address: SocketAddr, pool: DbPool) {
+    log::info!("Serving HTTP server in http://{}", address);
+    if SETTINGS.server.metrics {
+        metrics::register();
+    }
+    let service = make_service_fn(move |_| {
+        let pool: DbPool = pool.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |request| handler(request, pool.clone()))) }
+    });
+    if let Err(err) = Server::bind(&address).serve(service).await {
+        log::error!("HTTP server error: {}", err);
+    }
+}
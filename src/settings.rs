@@ -0,0 +1,375 @@
+// rpg_bot - Telegram bot 🤖, help you to run and share Rust code in Telegram via Rust playground 🦀
+// Source code: <https://github.com/TheAwiteb/rpg_bot>
+//
+// Copyright (C) 2022 TheAwiteb <awiteb@hotmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Bot settings, layered: the TOML settings file, then the environment variables override it,
+//! then the `config` table overrides the limits at runtime (with `/config`)
+//!
+//! The settings file is `rpg_bot.toml` by default, or `RPG_BOT_SETTINGS` environment variable,
+//! see `rpg_bot.example.toml`
+
+use crate::models::KNOWN_CONFIGS;
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use std::{env, fmt::Display, fs, io::ErrorKind, str::FromStr};
+
+/// The default settings file
+const SETTINGS_FILE: &str = "rpg_bot.toml";
+/// Maximum retries of the failed Rust playground requests, the bigger `retries` setting is clamped
+const MAX_RETRIES: u32 = 10;
+/// The supported languages, the languages of [`languages_ctx`](crate::rpg_db::languages_ctx)
+pub const LANGUAGES: [&str; 3] = ["العربية 🇸🇦", "English 🇺🇸", "русский 🇷🇺"];
+
+lazy_static! {
+    /// The bot settings, loaded on first use (after the `.env` file is loaded)
+    pub static ref SETTINGS: Settings = Settings::load();
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub telegram: TelegramSettings,
+    pub database: DatabaseSettings,
+    pub backend: BackendSettings,
+    pub server: ServerSettings,
+    pub limits: LimitsSettings,
+    pub i18n: I18nSettings,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelegramSettings {
+    /// The bot token (`TELOXIDE_TOKEN`)
+    pub token: Option<String>,
+    /// Telegram ids of the admins (`ADMINS`, comma separated)
+    pub admins: Vec<String>,
+    /// The public URL of the webhook, long polling is used if it's not set (`WEBHOOK_URL`)
+    pub webhook_url: Option<String>,
+    /// The address that the webhook server listens on (`WEBHOOK_ADDRESS`)
+    pub webhook_address: Option<String>,
+    /// The secret token of the webhook (`WEBHOOK_SECRET`)
+    pub webhook_secret: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseSettings {
+    /// SQLite database path or PostgreSQL url (`DATABASE_URL`)
+    pub url: Option<String>,
+    /// Maximum connections of the pool (`DATABASE_POOL_SIZE`)
+    pub pool_size: u32,
+    /// How long a SQLite connection waits for the database lock in milliseconds (`DATABASE_BUSY_TIMEOUT`)
+    pub busy_timeout: u32,
+    /// How long an update waits for a free connection of the pool in seconds (`DATABASE_CONNECTION_TIMEOUT`)
+    pub connection_timeout: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackendSettings {
+    /// Rust playground url (`PLAYGROUND_URL`)
+    pub playground_url: String,
+    /// Maximum concurrent Rust playground requests, the others wait in the queue (`PLAYGROUND_CONCURRENCY`)
+    pub concurrency: usize,
+    /// Maximum concurrent Rust playground requests of each user (`PLAYGROUND_USER_CONCURRENCY`)
+    pub user_concurrency: usize,
+    /// Rust playground request timeout in seconds (`PLAYGROUND_TIMEOUT`)
+    pub timeout: u64,
+    /// Rust playground connect timeout in seconds (`PLAYGROUND_CONNECT_TIMEOUT`)
+    pub connect_timeout: u64,
+    /// How many times the failed requests are retried, at most 10 (`PLAYGROUND_RETRIES`)
+    pub retries: u32,
+    /// The delay before the first retry in milliseconds, doubled every retry (`PLAYGROUND_RETRY_BACKOFF`)
+    pub retry_backoff: u64,
+    /// Consecutive failures that open the circuit breaker, `0` disables it (`PLAYGROUND_BREAKER_THRESHOLD`)
+    pub breaker_threshold: u32,
+    /// How long the circuit breaker stays open in seconds (`PLAYGROUND_BREAKER_COOLDOWN`)
+    pub breaker_cooldown: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    /// The address of the HTTP server (metrics and health check), disabled if it's not set (`HTTP_ADDRESS`)
+    pub http_address: Option<String>,
+    /// Serve the prometheus metrics in `/metrics` of the HTTP server (`METRICS_ENABLED`)
+    pub metrics: bool,
+    /// Check the Rust playground in the readiness check (`READY_CHECK_PLAYGROUND`)
+    pub ready_check_playground: bool,
+}
+
+/// The defaults of the known configs, the `config` table values override them
+/// (environment variables are `LIMIT_<NAME>`, like `LIMIT_COMMAND_DELAY`)
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsSettings {
+    pub attempts_window: Option<i64>,
+    pub button_delay: Option<i64>,
+    pub code_length: Option<i64>,
+    pub command_delay: Option<i64>,
+    pub events_retention: Option<i64>,
+    pub events_retention_interval: Option<i64>,
+    pub expire_interval: Option<i64>,
+    pub reset_attempts_interval: Option<i64>,
+    pub result_cache_ttl: Option<i64>,
+    pub stats_rollup_interval: Option<i64>,
+    pub time_limit_expiration: Option<i64>,
+    pub vacuum_interval: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct I18nSettings {
+    /// The language of the new users, and when the user language is unknown (`DEFAULT_LANGUAGE`)
+    pub default_language: String,
+}
+
+impl Default for DatabaseSettings {
+    fn default() -> Self {
+        Self {
+            url: None,
+            pool_size: 10,
+            busy_timeout: 5000,
+            connection_timeout: 10,
+        }
+    }
+}
+
+impl Default for BackendSettings {
+    fn default() -> Self {
+        Self {
+            playground_url: "https://play.rust-lang.org".into(),
+            concurrency: 4,
+            user_concurrency: 1,
+            timeout: 30,
+            connect_timeout: 5,
+            retries: 2,
+            retry_backoff: 500,
+            breaker_threshold: 5,
+            breaker_cooldown: 60,
+        }
+    }
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            http_address: None,
+            metrics: false,
+            ready_check_playground: true,
+        }
+    }
+}
+
+impl Default for I18nSettings {
+    fn default() -> Self {
+        Self {
+            default_language: "English 🇺🇸".into(),
+        }
+    }
+}
+
+/// Returns the value of the environment variable, `None` if it's not set or empty
+fn env_value<T: FromStr>(name: &str) -> Option<T>
+where
+    T::Err: Display,
+{
+    let value: String = env::var(name).ok().filter(|value| !value.is_empty())?;
+    Some(
+        value
+            .parse()
+            .unwrap_or_else(|err| panic!("Invalid `{}` environment variable: {}", name, err)),
+    )
+}
+
+/// Override the setting with the environment variable, if it's set
+fn override_with<T: FromStr>(setting: &mut T, name: &str)
+where
+    T::Err: Display,
+{
+    if let Some(value) = env_value(name) {
+        *setting = value;
+    }
+}
+
+/// Override the optional setting with the environment variable, if it's set
+fn override_option_with<T: FromStr>(setting: &mut Option<T>, name: &str)
+where
+    T::Err: Display,
+{
+    if let Some(value) = env_value(name) {
+        *setting = Some(value);
+    }
+}
+
+impl LimitsSettings {
+    /// Returns the default of the known config from the settings, `None` if it's not set
+    pub fn get(&self, name: &str) -> Option<i64> {
+        match name {
+            "attempts_window" => self.attempts_window,
+            "button_delay" => self.button_delay,
+            "code_length" => self.code_length,
+            "command_delay" => self.command_delay,
+            "events_retention" => self.events_retention,
+            "events_retention_interval" => self.events_retention_interval,
+            "expire_interval" => self.expire_interval,
+            "reset_attempts_interval" => self.reset_attempts_interval,
+            "result_cache_ttl" => self.result_cache_ttl,
+            "stats_rollup_interval" => self.stats_rollup_interval,
+            "time_limit_expiration" => self.time_limit_expiration,
+            "vacuum_interval" => self.vacuum_interval,
+            _ => None,
+        }
+    }
+
+    fn override_with_env(&mut self) {
+        for (limit, name) in [
+            (&mut self.attempts_window, "LIMIT_ATTEMPTS_WINDOW"),
+            (&mut self.button_delay, "LIMIT_BUTTON_DELAY"),
+            (&mut self.code_length, "LIMIT_CODE_LENGTH"),
+            (&mut self.command_delay, "LIMIT_COMMAND_DELAY"),
+            (&mut self.events_retention, "LIMIT_EVENTS_RETENTION"),
+            (
+                &mut self.events_retention_interval,
+                "LIMIT_EVENTS_RETENTION_INTERVAL",
+            ),
+            (&mut self.expire_interval, "LIMIT_EXPIRE_INTERVAL"),
+            (
+                &mut self.reset_attempts_interval,
+                "LIMIT_RESET_ATTEMPTS_INTERVAL",
+            ),
+            (&mut self.result_cache_ttl, "LIMIT_RESULT_CACHE_TTL"),
+            (
+                &mut self.stats_rollup_interval,
+                "LIMIT_STATS_ROLLUP_INTERVAL",
+            ),
+            (
+                &mut self.time_limit_expiration,
+                "LIMIT_TIME_LIMIT_EXPIRATION",
+            ),
+            (&mut self.vacuum_interval, "LIMIT_VACUUM_INTERVAL"),
+        ] {
+            override_option_with(limit, name);
+        }
+    }
+}
+
+impl Settings {
+    /// Load the settings file (if exist) and override it with the environment variables,
+    /// panics if the settings file or a setting value is invalid
+    fn load() -> Self {
+        let path: String =
+            env_value("RPG_BOT_SETTINGS").unwrap_or_else(|| SETTINGS_FILE.to_string());
+        let mut settings: Self = match fs::read_to_string(&path) {
+            Ok(content) => toml::from_str(&content)
+                .unwrap_or_else(|err| panic!("Invalid settings file `{}`: {}", path, err)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Self::default(),
+            Err(err) => panic!("Cannot read the settings file `{}`: {}", path, err),
+        };
+        settings.override_with_env();
+        settings.validate();
+        settings
+    }
+
+    /// Panics if a setting value is invalid, the values of the known configs (`limits`) should be
+    /// in their ranges and the default language should be supported
+    fn validate(&self) {
+        for known in KNOWN_CONFIGS.iter() {
+            if let Some(value) = self.limits.get(known.name) {
+                if let Err(err) = known.validate(&value.to_string()) {
+                    panic!("Invalid `limits` setting: {}", err);
+                }
+            }
+        }
+        if !LANGUAGES.contains(&self.i18n.default_language.as_str()) {
+            panic!(
+                "Invalid `default_language` setting `{}`, it should be one of: {}",
+                self.i18n.default_language,
+                LANGUAGES.join(", ")
+            );
+        }
+    }
+
+    fn override_with_env(&mut self) {
+        let telegram: &mut TelegramSettings = &mut self.telegram;
+        override_option_with(&mut telegram.token, "TELOXIDE_TOKEN");
+        if let Some(admins) = env_value::<String>("ADMINS") {
+            telegram.admins = admins
+                .split(',')
+                .map(str::trim)
+                .filter(|telegram_id| !telegram_id.is_empty())
+                .map(String::from)
+                .collect();
+        }
+        override_option_with(&mut telegram.webhook_url, "WEBHOOK_URL");
+        override_option_with(&mut telegram.webhook_address, "WEBHOOK_ADDRESS");
+        override_option_with(&mut telegram.webhook_secret, "WEBHOOK_SECRET");
+
+        override_option_with(&mut self.database.url, "DATABASE_URL");
+        override_with(&mut self.database.pool_size, "DATABASE_POOL_SIZE");
+        override_with(&mut self.database.busy_timeout, "DATABASE_BUSY_TIMEOUT");
+        override_with(
+            &mut self.database.connection_timeout,
+            "DATABASE_CONNECTION_TIMEOUT",
+        );
+        // zero timeout is rejected by the pool
+        self.database.connection_timeout = self.database.connection_timeout.max(1);
+
+        override_with(&mut self.backend.playground_url, "PLAYGROUND_URL");
+        self.backend.playground_url = self.backend.playground_url.trim_end_matches('/').into();
+        override_with(&mut self.backend.concurrency, "PLAYGROUND_CONCURRENCY");
+        override_with(
+            &mut self.backend.user_concurrency,
+            "PLAYGROUND_USER_CONCURRENCY",
+        );
+        override_with(&mut self.backend.timeout, "PLAYGROUND_TIMEOUT");
+        override_with(
+            &mut self.backend.connect_timeout,
+            "PLAYGROUND_CONNECT_TIMEOUT",
+        );
+        override_with(&mut self.backend.retries, "PLAYGROUND_RETRIES");
+        override_with(&mut self.backend.retry_backoff, "PLAYGROUND_RETRY_BACKOFF");
+        override_with(
+            &mut self.backend.breaker_threshold,
+            "PLAYGROUND_BREAKER_THRESHOLD",
+        );
+        override_with(
+            &mut self.backend.breaker_cooldown,
+            "PLAYGROUND_BREAKER_COOLDOWN",
+        );
+        self.backend.retries = self.backend.retries.min(MAX_RETRIES);
+        // zero would block all the requests
+        self.backend.concurrency = self.backend.concurrency.max(1);
+        self.backend.user_concurrency = self.backend.user_concurrency.max(1);
+        // the running requests shouldn't take all the connections of the pool
+        self.database.pool_size = self
+            .database
+            .pool_size
+            .max(self.backend.concurrency as u32 + 2);
+
+        override_option_with(&mut self.server.http_address, "HTTP_ADDRESS");
+        override_with(&mut self.server.metrics, "METRICS_ENABLED");
+        override_with(
+            &mut self.server.ready_check_playground,
+            "READY_CHECK_PLAYGROUND",
+        );
+
+        self.limits.override_with_env();
+
+        override_with(&mut self.i18n.default_language, "DEFAULT_LANGUAGE");
+    }
+}
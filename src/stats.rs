@@ -0,0 +1,194 @@
+// rpg_bot - Telegram bot 🤖, help you to run and share Rust code in Telegram via Rust playground 🦀
+// Source code: <https://github.com/TheAwiteb/rpg_bot>
+//
+// Copyright (C) 2022 TheAwiteb <awiteb@hotmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{
+    errors::RpgResult,
+    models::{Config, DieselResult, Event, EventStats},
+    rpg_db::{languages_ctx, DbConnection},
+};
+use chrono::{offset, Duration, NaiveDateTime};
+use json_gettext::get_text;
+use lazy_static::lazy_static;
+use std::{collections::HashMap, sync::RwLock};
+use strfmt::strfmt;
+
+/// Statistics periods, with their days (`None` means all the time)
+pub const PERIODS: [(&str, Option<i64>); 4] = [
+    ("day", Some(1)),
+    ("week", Some(7)),
+    ("month", Some(30)),
+    ("all", None),
+];
+/// Default statistics period
+pub const DEFAULT_PERIOD: &str = "week";
+/// Number of the most used versions/editions in the statistics
+const TOP_MAXIMUM: i64 = 3;
+
+/// Statistics variables with their computing time
+type Rollup = (NaiveDateTime, HashMap<String, String>);
+
+lazy_static! {
+    /// The statistics of the periods, computed by the stats rollup job
+    static ref ROLLUPS: RwLock<HashMap<&'static str, Rollup>> = RwLock::new(HashMap::new());
+}
+
+/// Percentiles of the Rust playground requests durations in the statistics
+const PERCENTILES: [i64; 3] = [50, 90, 99];
+
+/// Returns the most used values with their count, like `stable (10), nightly (2)`
+fn most_used(counts: &[(String, i64)]) -> String {
+    if counts.is_empty() {
+        "-".into()
+    } else {
+        counts
+            .iter()
+            .map(|(value, count)| format!("{} ({})", value, count))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+/// Returns the statistics variables of the period, `days` is the period days (`None` means all the time)
+fn stats_vars(
+    days: Option<i64>,
+    now: NaiveDateTime,
+    conn: &mut DbConnection,
+) -> DieselResult<HashMap<String, String>> {
+    let stats: EventStats = Event::stats(
+        days.map(|days| now - Duration::days(days)),
+        TOP_MAXIMUM,
+        &PERCENTILES,
+        conn,
+    )?;
+    // the number of the events that match the filter
+    let count = |filter: &dyn Fn(&str, bool, bool) -> bool| -> i64 {
+        stats
+            .counts
+            .iter()
+            .filter(|(kind, is_cached, success, _)| filter(kind, *is_cached, *success))
+            .map(|(_, _, _, count)| count)
+            .sum()
+    };
+    let not_cached: i64 = count(&|kind, is_cached, _| kind != "callback" && !is_cached);
+    let failures: i64 =
+        count(&|kind, is_cached, success| kind != "callback" && !is_cached && !success);
+
+    // the days of all the time period are from the first event
+    let period_days: i64 = days.unwrap_or_else(|| {
+        stats
+            .first_event
+            .map(|first| (now - first).num_days() + 1)
+            .unwrap_or(1)
+    });
+
+    let mut vars: HashMap<String, String> = HashMap::new();
+    vars.insert(
+        "runs".into(),
+        count(&|kind, _, _| kind == "run").to_string(),
+    );
+    vars.insert(
+        "shares".into(),
+        count(&|kind, _, _| kind == "share").to_string(),
+    );
+    vars.insert(
+        "callbacks".into(),
+        count(&|kind, _, _| kind == "callback").to_string(),
+    );
+    vars.insert(
+        "cached".into(),
+        count(&|kind, is_cached, _| kind != "callback" && is_cached).to_string(),
+    );
+    vars.insert("active_users".into(), stats.active_users.to_string());
+    vars.insert(
+        "daily_active_users".into(),
+        format!("{:.1}", stats.user_days as f64 / period_days as f64),
+    );
+    vars.insert("versions".into(), most_used(&stats.versions));
+    vars.insert("editions".into(), most_used(&stats.editions));
+    vars.insert(
+        "failure_rate".into(),
+        if not_cached == 0 {
+            "-".into()
+        } else {
+            format!("{:.1}%", failures as f64 * 100.0 / not_cached as f64)
+        },
+    );
+    for (percentile, duration) in PERCENTILES.iter().zip(&stats.percentiles) {
+        vars.insert(
+            format!("p{}", percentile),
+            duration
+                .map(|duration| duration.to_string())
+                .unwrap_or_else(|| "-".into()),
+        );
+    }
+    Ok(vars)
+}
+
+/// Compute the statistics of all periods, used by the stats rollup job,
+/// returns the number of the computed periods
+pub fn rollup(conn: &mut DbConnection) -> RpgResult<usize> {
+    let now: NaiveDateTime = offset::Utc::now().naive_utc();
+    let mut rollups: HashMap<&'static str, Rollup> = HashMap::new();
+    for (period, days) in PERIODS {
+        rollups.insert(period, (now, stats_vars(days, now, conn)?));
+    }
+    *ROLLUPS.write().unwrap() = rollups;
+    Ok(PERIODS.len())
+}
+
+/// Returns the statistics message of the period, `None` if the period is unknown
+///
+/// The statistics of the stats rollup job are used if it's enabled, otherwise they are computed now
+pub fn stats_text(
+    period: &str,
+    language: &str,
+    conn: &mut DbConnection,
+) -> DieselResult<Option<String>> {
+    let ctx = languages_ctx();
+    let days: Option<i64> = match PERIODS.iter().find(|(name, _)| name == &period) {
+        Some((_, days)) => *days,
+        None => return Ok(None),
+    };
+    let rollup: Option<Rollup> = if Config::integer("stats_rollup_interval", conn) > 0 {
+        ROLLUPS.read().unwrap().get(period).cloned()
+    } else {
+        None
+    };
+    let (updated, mut vars) = match rollup {
+        Some(rollup) => rollup,
+        None => {
+            let now: NaiveDateTime = offset::Utc::now().naive_utc();
+            (now, stats_vars(days, now, conn)?)
+        }
+    };
+    vars.insert("period".into(), period.into());
+    vars.insert(
+        "updated".into(),
+        updated.format("%Y-%m-%d %H:%M UTC").to_string(),
+    );
+
+    Ok(Some(
+        strfmt(
+            &get_text!(ctx, language, "STATS_MESSAGE")
+                .unwrap()
+                .to_string(),
+            &vars,
+        )
+        .unwrap(),
+    ))
+}
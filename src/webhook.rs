@@ -0,0 +1,207 @@
+// rpg_bot - Telegram bot 🤖, help you to run and share Rust code in Telegram via Rust playground 🦀
+// Source code: <https://github.com/TheAwiteb/rpg_bot>
+//
+// Copyright (C) 2022 TheAwiteb <awiteb@hotmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::settings::{TelegramSettings, SETTINGS};
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use reqwest::Url;
+use serde::Deserialize;
+use std::{convert::Infallible, net::SocketAddr};
+use teloxide::{
+    dispatching::{
+        stop_token::AsyncStopToken,
+        update_listeners::{StatefulListener, UpdateListener},
+    },
+    prelude2::*,
+    types::Update,
+};
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+/// The header that telegram sends the secret token in
+const SECRET_TOKEN_HEADER: &str = "X-Telegram-Bot-Api-Secret-Token";
+
+/// Webhook settings
+pub struct WebhookConfig {
+    /// The address that the webhook server listens on (behind the reverse proxy)
+    pub address: SocketAddr,
+    /// The public URL that telegram sends the updates to
+    pub url: Url,
+    /// The secret token that telegram sends in every request
+    pub secret_token: String,
+}
+
+#[derive(Deserialize)]
+struct TelegramResponse {
+    ok: bool,
+    description: Option<String>,
+}
+
+impl WebhookConfig {
+    /// Returns the webhook settings from the bot settings, `None` if `WEBHOOK_URL` is not set
+    /// (long polling will be used)
+    pub fn from_settings() -> Option<Self> {
+        let telegram: &TelegramSettings = &SETTINGS.telegram;
+        let url: &str = telegram.webhook_url.as_deref()?;
+        let secret_token: String = telegram
+            .webhook_secret
+            .clone()
+            .expect("WEBHOOK_SECRET must be set with WEBHOOK_URL");
+        // https://core.telegram.org/bots/api#setwebhook
+        if !(1..=256).contains(&secret_token.len())
+            || !secret_token
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            panic!(
+                "WEBHOOK_SECRET should be 1-256 characters, only `A-Z`, `a-z`, `0-9`, `_` and `-`"
+            );
+        }
+
+        Some(Self {
+            address: telegram
+                .webhook_address
+                .as_deref()
+                .unwrap_or("0.0.0.0:8443")
+                .parse()
+                .expect("WEBHOOK_ADDRESS should be socket address like `0.0.0.0:8443`"),
+            url: Url::parse(url).expect("WEBHOOK_URL should be valid URL"),
+            secret_token,
+        })
+    }
+}
+
+/// Set the bot webhook with the secret token
+///
+/// The request is sent directly because `SetWebhook` of teloxide doesn't support the secret token
+async fn set_webhook(bot: &AutoSend<Bot>, config: &WebhookConfig) -> Result<(), String> {
+    let mut api_url: Url = bot.inner().api_url();
+    api_url.set_path(&format!("bot{}/setWebhook", bot.inner().token()));
+
+    let response: TelegramResponse = reqwest::Client::new()
+        .post(api_url)
+        .json(&serde_json::json!({
+            "url": config.url.as_str(),
+            "secret_token": config.secret_token,
+        }))
+        .send()
+        .await
+        .map_err(|err| err.to_string())?
+        .json()
+        .await
+        .map_err(|err| err.to_string())?;
+
+    if response.ok {
+        Ok(())
+    } else {
+        Err(response.description.unwrap_or_default())
+    }
+}
+
+/// Webhook request handler, accept only `POST` requests to the webhook URL path with the secret token
+async fn handler(
+    request: Request<Body>,
+    path: String,
+    secret_token: String,
+    sender: UnboundedSender<Result<Update, Infallible>>,
+) -> Result<Response<Body>, Infallible> {
+    let status: StatusCode = if request.method() != Method::POST || request.uri().path() != path {
+        StatusCode::NOT_FOUND
+    } else if request
+        .headers()
+        .get(SECRET_TOKEN_HEADER)
+        .map(|token| token.as_bytes())
+        != Some(secret_token.as_bytes())
+    {
+        log::warn!("Webhook request without a valid secret token");
+        StatusCode::UNAUTHORIZED
+    } else {
+        match hyper::body::to_bytes(request.into_body())
+            .await
+            .map_err(|err| err.to_string())
+            .and_then(|body| serde_json::from_slice::<Update>(&body).map_err(|err| err.to_string()))
+        {
+            // the dispatcher is stopped (shutting down), telegram will send the update again
+            Ok(update) => match sender.send(Ok(update)) {
+                Ok(()) => StatusCode::OK,
+                Err(err) => {
+                    log::error!("Cannot send an incoming update from the webhook: {}", err);
+                    StatusCode::SERVICE_UNAVAILABLE
+                }
+            },
+            Err(err) => {
+                log::error!("Invalid webhook update: {}", err);
+                StatusCode::BAD_REQUEST
+            }
+        }
+    };
+
+    Ok(Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap())
+}
+
+/// Set the webhook and serve the webhook server, returns its updates listener
+pub async fn listener(
+    bot: &AutoSend<Bot>,
+    config: WebhookConfig,
+) -> impl UpdateListener<Infallible> {
+    set_webhook(bot, &config)
+        .await
+        .unwrap_or_else(|err| panic!("Cannot setup a webhook: {}", err));
+    log::info!(
+        "Listening for webhook updates in http://{} ({})",
+        config.address,
+        config.url
+    );
+
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let (stop_token, stop_flag) = AsyncStopToken::new_pair();
+    let path: String = config.url.path().to_string();
+    let secret_token: String = config.secret_token;
+
+    let service = make_service_fn(move |_| {
+        let (path, secret_token, sender) = (path.clone(), secret_token.clone(), sender.clone());
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handler(request, path.clone(), secret_token.clone(), sender.clone())
+            }))
+        }
+    });
+    let server = Server::bind(&config.address)
+        .serve(service)
+        .with_graceful_shutdown(stop_flag);
+    tokio::spawn(async move {
+        if let Err(err) = server.await {
+            log::error!("Webhook server error: {}", err);
+        }
+    });
+
+    fn stream<S, T>(state: &mut (S, T)) -> &mut S {
+        &mut state.0
+    }
+
+    StatefulListener::new(
+        (UnboundedReceiverStream::new(receiver), stop_token),
+        stream,
+        |state: &mut (_, AsyncStopToken)| state.1.clone(),
+    )
+}